@@ -1,17 +1,35 @@
 //! Stores provided user input and requests desired output.
 
+use crate::matcher::{BinaryDetection, Config, Matcher, MatcherBuilder};
+use crate::mmap::MmapChoice;
+use crate::parallel::Pool;
 use crate::search::Searcher;
+use crate::walk::{self, WalkConfig};
 use crate::writer::Writer;
-use crate::matcher::MatcherBuilder;
-use std::io::{BufRead, Write};
-use std::path::PathBuf;
+use anyhow::Context;
+use bstr::ByteSlice;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
 use structopt::{StructOpt, clap::AppSettings};
 
+/// A PATH which refers to stdin rather than to a file
+const STDIN_PATH: &str = "-";
+
+/// Shown in place of a file path for matches read from stdin
+const STDIN_LABEL: &str = "(standard input)";
+
 const ABOUT: &str = "
 sp is a very basic implementation of grep. Use -h for more information.";
 
 const USAGE: &str = "
-    sp [OPTIONS] <PATTERN> <PATH>";
+    sp [OPTIONS] <PATTERN> [PATH ...]
+    sp [OPTIONS] -e <PATTERN> ... [PATH ...]
+    sp [OPTIONS] -f <FILE> ... [PATH ...]
+    sp --type-list";
 
 const TEMPLATE: &str = "\
 {bin} {version}
@@ -28,53 +46,301 @@ OPTIONS:
 // AppSettings::DeriveDisplayOrder might be helpful for custom ordering
 // AppSettings::HidePossibleValuesInHelp for concise usage message
 /// Stores provided user input including any specified options.
+#[derive(StructOpt, Debug)]
 #[structopt(rename_all = "kebab-case", about = ABOUT, usage = USAGE, 
     template = TEMPLATE, 
     global_settings(&[AppSettings::UnifiedHelpMessage]))]
-#[derive(StructOpt, Debug)]
 pub struct Input {
     /// A pattern used for matching a sub-slice
     #[structopt(
         name = "PATTERN",
+        required_unless_one = &["regexp", "file", "type-list"],
         help = "A pattern used for matching a sub-slice",
-        long_help = "A pattern used for matching a sub-slice"
+        long_help = "A pattern used for matching a sub-slice. If --regexp or \
+                     --file is given, this is the first PATH instead"
     )]
-    pub pattern: String,
+    pub pattern: Option<String>,
     // TODO: pattern should be optional if -c is provided
 
-    /// A file to search
+    /// Files or directories to search
     #[structopt(
         name = "PATH",
         parse(from_os_str),
-        help = "A file to search",
-        long_help = "A file to search"
+        help = "Files or directories to search (- for stdin)",
+        long_help = "Files or directories to search. Directories are searched \
+                     recursively. If no PATH is given or PATH is -, stdin is \
+                     searched. Matches are prefixed with their file path \
+                     whenever more than one file is searched"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Show NUM lines after each match
+    #[structopt(short = "A", long, value_name = "NUM")]
+    pub after_context: Option<usize>,
+
+    /// What to do with files containing a NUL byte in their first block
+    ///
+    /// With quit, the search stops at the first match and only reports that
+    /// the file matches. With skip, the file is not searched at all. With
+    /// text, the file is searched like any other
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        default_value = "quit",
+        possible_values = &["quit", "skip", "text"]
     )]
-    pub path: PathBuf,
+    pub binary: BinaryDetection,
+
+    /// Show NUM lines before each match
+    #[structopt(short = "B", long, value_name = "NUM")]
+    pub before_context: Option<usize>,
+
+    /// Show NUM lines before and after each match
+    ///
+    /// This is overridden by --after-context and --before-context
+    #[structopt(short = "C", long, value_name = "NUM")]
+    pub context: Option<usize>,
+
+    /// Show the byte offset of each line in the input
+    ///
+    /// With --only-matching, the byte offset of each match is shown instead
+    #[structopt(short = "b", long)]
+    pub byte_offset: bool,
+
+    /// When to highlight matches, line numbers, paths and separators
+    ///
+    /// With auto, colors are only used if stdout is a terminal
+    #[structopt(
+        long,
+        value_name = "WHEN",
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    pub color: ColorChoice,
+
+    /// Show the column of the first match in each line
+    ///
+    /// Columns are 1-based byte offsets within the line. With
+    /// --only-matching, the column of each match is shown instead
+    #[structopt(long)]
+    pub column: bool,
 
     /// Suppress normal output and show number of matching lines
     #[structopt(short, long)]
     pub count: bool,
 
+    /// Suppress normal output and show number of matches
+    ///
+    /// Every occurrence of a pattern is counted, not just matching lines.
+    /// With --words, --starts-with or --ends-with, matching words or fields
+    /// are counted. With --invert-match, non-matching lines are counted
+    #[structopt(long)]
+    pub count_matches: bool,
+
     /// Only show matches containing fields ending with PATTERN
-    #[structopt(short, long)]
+    ///
+    /// This flag has no short form, -e is short for --regexp
+    #[structopt(long)]
     pub ends_with: bool,
 
+    /// Read patterns from FILE, one per line (- for stdin)
+    ///
+    /// Can be given multiple times and combined with --regexp. A line
+    /// matches if any of the patterns matches
+    #[structopt(
+        short,
+        long,
+        value_name = "FILE",
+        number_of_values = 1,
+        parse(from_os_str)
+    )]
+    pub file: Vec<PathBuf>,
+
+    /// Only show the paths of files with at least one match
+    ///
+    /// Each file is only read up to its first match. This flag overrides
+    /// --count
+    #[structopt(short = "l", long, conflicts_with = "files-without-match")]
+    pub files_with_matches: bool,
+
+    /// Only show the paths of files without any match
+    ///
+    /// Each file is only read up to its first match. This flag overrides
+    /// --count
+    #[structopt(long)]
+    pub files_without_match: bool,
+
+    /// Follow symbolic links while searching directories
+    #[structopt(short = "L", long)]
+    pub follow: bool,
+
+    /// Only search files matching GLOB while searching directories
+    ///
+    /// Can be given multiple times. Files matching a GLOB prefixed with ! are
+    /// skipped. Globs are matched relative to the searched directory and
+    /// take precedence over ignore files
+    #[structopt(short, long, value_name = "GLOB", number_of_values = 1)]
+    pub glob: Vec<String>,
+
+    /// Search hidden files and directories
+    ///
+    /// Files and directories whose name starts with a dot are skipped by
+    /// default while searching directories
+    #[structopt(long)]
+    pub hidden: bool,
+
     /// Case insensitive search
+    ///
+    /// Full Unicode case folding is used, so e.g. straße matches STRASSE.
+    /// With --regex, only simple case folding is used, which maps each
+    /// character to a single one, so straße does not match STRASSE
     #[structopt(short, long)]
     pub ignore_case: bool,
 
+    /// Like --glob, but GLOB is matched case insensitively
+    #[structopt(long, value_name = "GLOB", number_of_values = 1)]
+    pub iglob: Vec<String>,
+
+    /// Only show lines which do not match
+    ///
+    /// Applies to all other options, so --count counts non-matching lines
+    /// and --max-count limits the number of non-matching lines shown
+    #[structopt(short = "v", long)]
+    pub invert_match: bool,
+
+    /// Only show lines which match PATTERN as a whole
+    ///
+    /// Line terminators are ignored. This flag overrides --starts-with,
+    /// --ends-with and --words. With --regex, the regex has to match the
+    /// whole line
+    #[structopt(short = "x", long)]
+    pub line_regexp: bool,
+
+    /// Replace invalid UTF-8 in printed lines with U+FFFD
+    ///
+    /// By default lines are printed as raw bytes, exactly as they were read
+    #[structopt(long)]
+    pub lossy: bool,
+
     /// Limit number of shown matches
     #[structopt(short, long, value_name="NUM")]
     pub max_count: Option<u64>,
 
+    /// Do not respect ignore files
+    ///
+    /// By default, files matched by .gitignore, .ignore and .spignore files
+    /// are skipped while searching directories. Rules in .spignore take
+    /// precedence over .ignore, which takes precedence over .gitignore
+    #[structopt(long)]
+    pub no_ignore: bool,
+
+    /// Search files through a memory map
+    ///
+    /// By default, only files of at least 1 MiB are memory mapped. Files
+    /// which cannot be mapped, like stdin, are always read
+    #[structopt(long, overrides_with = "no-mmap")]
+    pub mmap: bool,
+
+    /// Never search files through a memory map
+    #[structopt(long, overrides_with = "mmap")]
+    pub no_mmap: bool,
+
     /// Suppress line numbers which are shown by default
     #[structopt(short, long)]
     pub no_line_number: bool,
 
+    /// Print results as JSON Lines
+    ///
+    /// Every line is a JSON object describing one event: the begin and end of
+    /// each file with matches, each matching or context line, each count and
+    /// a final summary. Lines and paths which are not valid UTF-8 are base64
+    /// encoded. Options which only affect the look of the output are ignored
+    #[structopt(long)]
+    pub json: bool,
+
+    /// Only show the matched parts of matching lines
+    ///
+    /// Every match is shown on its own line, so the line number is repeated
+    /// for lines with multiple matches. Context lines are not shown
+    #[structopt(short, long)]
+    pub only_matching: bool,
+
+    /// Treat PATTERN as a regular expression
+    ///
+    /// Matching is done on raw bytes, so lines do not need to be valid UTF-8.
+    /// This flag cannot be combined with --starts-with, --ends-with or --words
+    #[structopt(
+        short = "E",
+        long,
+        conflicts_with_all = &["ends-with", "starts-with", "words"]
+    )]
+    pub regex: bool,
+
+    /// Use PATTERN for matching
+    ///
+    /// Can be given multiple times and combined with --file. A line matches
+    /// if any of the patterns matches. This is useful for patterns starting
+    /// with a dash
+    #[structopt(short = "e", long, value_name = "PATTERN", number_of_values = 1)]
+    pub regexp: Vec<String>,
+
+    /// Case insensitive search if all patterns are lowercase
+    ///
+    /// Any uppercase character, including non-ASCII ones, makes the search
+    /// case sensitive. Escape sequences like \W in a regex do not count.
+    /// --ignore-case overrides this flag
+    #[structopt(short = "S", long)]
+    pub smart_case: bool,
+
+    /// Print results sorted by SORTBY
+    ///
+    /// Results of different PATHs are always printed in the order in which
+    /// the PATHs are given. When searching in parallel, the files found in a
+    /// directory are printed as soon as they have been searched. With path,
+    /// they are printed sorted by path instead
+    #[structopt(long, value_name = "SORTBY", possible_values = &["path"])]
+    pub sort: Option<SortBy>,
+
     /// Only show matches containing fields starting with PATTERN
     #[structopt(short, long)]
     pub starts_with: bool,
 
+    /// Search binary files as if they were text
+    ///
+    /// This is equivalent to --binary=text and overrides --binary
+    #[structopt(short = "a", long)]
+    pub text: bool,
+
+    /// Search up to NUM files in parallel
+    ///
+    /// Defaults to the number of available CPUs. The results of different
+    /// files are never interleaved. See --sort for the order in which they
+    /// are printed
+    #[structopt(short = "j", long, value_name = "NUM")]
+    pub threads: Option<usize>,
+
+    /// Only search files of type TYPE while searching directories
+    ///
+    /// Can be given multiple times. See --type-list for all types
+    #[structopt(short = "t", long = "type", value_name = "TYPE", number_of_values = 1)]
+    pub type_: Vec<String>,
+
+    /// Add a file type defined as NAME:GLOB, e.g. 'proto:*.proto'
+    ///
+    /// Can be given multiple times. Adding to an existing type extends it
+    #[structopt(long, value_name = "TYPESPEC", number_of_values = 1)]
+    pub type_add: Vec<String>,
+
+    /// Show all file types and their globs
+    #[structopt(long)]
+    pub type_list: bool,
+
+    /// Do not search files of type TYPE while searching directories
+    ///
+    /// Can be given multiple times
+    #[structopt(short = "T", long, value_name = "TYPE", number_of_values = 1)]
+    pub type_not: Vec<String>,
+
     /// Whole words search (i.e. non-word characters are stripped)
     ///
     /// This flag overrides --starts-with and --ends-with and is
@@ -83,6 +349,59 @@ pub struct Input {
     pub words: bool,
 }
 
+/// Whether output is colored, as requested by --color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Use colors if stdout is a terminal
+    Auto,
+    /// Always use colors
+    Always,
+    /// Never use colors
+    Never,
+}
+
+impl ColorChoice {
+    fn use_color(self) -> bool {
+        match self {
+            Self::Auto => io::stdout().is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => anyhow::bail!("Unknown color choice {:?}", s),
+        }
+    }
+}
+
+/// How results are ordered, as requested by --sort
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Print results in the order in which files are found, i.e. sorted by
+    /// path within each directory
+    Path,
+}
+
+impl FromStr for SortBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Self::Path),
+            _ => anyhow::bail!("Unknown sort order {:?}", s),
+        }
+    }
+}
+
 /// A convenient type alias holding the returned result. In case of an `Err`,
 /// the error will be propagated and displayed in a human-readable way.
 pub type Output = anyhow::Result<(), anyhow::Error>;
@@ -90,31 +409,284 @@ pub type Output = anyhow::Result<(), anyhow::Error>;
 impl Input {
     /// # Errors
     ///
-    /// Will return `Err` if there was a problem reading from the underlying
-    /// reader or if UTF-8 conversion failed
-    pub fn show_matches(self, mut reader: impl BufRead, writer: impl Write) -> Output {
+    /// Will return `Err` if there was a problem writing the results. Files
+    /// which cannot be opened are reported and skipped; an `Err` is returned
+    /// once all other files have been searched.
+    pub fn show_matches(self, mut writer: impl Write) -> Output {
+        let walk_config = WalkConfig {
+            hidden: self.hidden,
+            no_ignore: self.no_ignore,
+            follow: self.follow,
+            globs: self.glob,
+            iglobs: self.iglob,
+            type_add: self.type_add,
+            types: self.type_,
+            types_not: self.type_not,
+        };
+
+        if self.type_list {
+            for def in walk::types(&walk_config)?.definitions() {
+                writeln!(writer, "{}: {}", def.name(), def.globs().join(", "))?;
+            }
+            return Ok(());
+        }
+
+        let mut paths = self.paths;
+        let patterns = if self.regexp.is_empty() && self.file.is_empty() {
+            // PATTERN is required unless --regexp or --file is given
+            self.pattern.into_iter().collect()
+        } else {
+            // Without PATTERN, the first positional argument is a PATH
+            paths.splice(0..0, self.pattern.map(PathBuf::from));
+            let mut patterns = self.regexp;
+            for file in &self.file {
+                patterns.extend(read_patterns(file)?);
+            }
+            patterns
+        };
 
         let matcher = MatcherBuilder::new()
-            .count(self.count)
+            .after_context(self.after_context.or(self.context).unwrap_or(0))
+            .before_context(self.before_context.or(self.context).unwrap_or(0))
+            .binary(if self.text {
+                BinaryDetection::Text
+            } else {
+                self.binary
+            })
+            .byte_offset(self.byte_offset)
+            .color(self.color.use_color())
+            .column(self.column)
+            .count(self.count || self.count_matches)
+            .count_matches(self.count_matches)
             .ends_with(self.ends_with)
+            .files_with_matches(self.files_with_matches)
+            .files_without_match(self.files_without_match)
             .ignore_case(self.ignore_case)
+            .invert(self.invert_match)
+            .json(self.json)
+            .line_regexp(self.line_regexp)
+            .lossy(self.lossy)
             .max_count(self.max_count)
             .no_line_number(self.no_line_number)
+            .only_matching(self.only_matching)
+            .regex(self.regex)
+            .smart_case(self.smart_case)
             .starts_with(self.starts_with)
             .words(self.words)
-            .build(self.pattern);
+            .build_many(patterns)?;
+
+        if paths.is_empty() {
+            paths.push(PathBuf::from(STDIN_PATH));
+        }
+        // JSON output and listed files always carry the path
+        let with_path = self.json
+            || matcher.config.lists_files()
+            || paths.len() > 1
+            || paths.iter().any(|path| path.is_dir());
+
+        let threads = self.threads.unwrap_or_else(|| {
+            thread::available_parallelism().map_or(1, NonZeroUsize::get)
+        });
+        // A single input is searched on the main thread so that its results
+        // are printed as soon as they are found
+        let parallel = threads > 1 && (paths.len() > 1 || paths.iter().any(|path| path.is_dir()));
+
+        let mmap = match (self.mmap, self.no_mmap) {
+            (true, _) => MmapChoice::Always,
+            (false, true) => MmapChoice::Never,
+            (false, false) => MmapChoice::Auto,
+        };
 
-        let searcher = Searcher {
-            reader: &mut reader,
-            matcher: &matcher,
+        let mut wrt = Writer::new(writer);
+        let failed = if parallel {
+            let sorted = self.sort == Some(SortBy::Path);
+            let search = |wrt: &mut Writer<Vec<u8>>, target: Target| {
+                search_target(wrt, &matcher, mmap, &target)
+            };
+            thread::scope(|scope| {
+                let mut pool = Pool::new(scope, threads, sorted, &search);
+                // Results of different PATHs are printed in the order in which
+                // they were given
+                let walk_failed = for_each_target(&paths, &walk_config, with_path, |target| {
+                    let group = target.arg;
+                    pool.submit(target, group, &mut wrt)
+                })?;
+                Ok::<_, anyhow::Error>(walk_failed + pool.finish(&mut wrt)?)
+            })?
+        } else {
+            let mut search_failed = 0;
+            let walk_failed = for_each_target(&paths, &walk_config, with_path, |target| {
+                let searched = search_target(&mut wrt, &matcher, mmap, &target)?;
+                search_failed += usize::from(!searched);
+                Ok(())
+            })?;
+            walk_failed + search_failed
         };
 
-        let wrt = Writer { wrt: writer };
-        let found = searcher.search_matches();
+        if self.json {
+            wrt.json_summary()?;
+        }
 
-        wrt.print_matches(found, &matcher.config)?;
+        if failed > 0 {
+            anyhow::bail!("{} file(s) could not be searched", failed);
+        }
 
         // Return () on success
         Ok(())
     }
 }
+
+/// A single input to search; stdin if `path` is -
+#[derive(Debug)]
+struct Target {
+    path: PathBuf,
+    /// Whether matches are prefixed with the path
+    with_path: bool,
+    /// Index of the PATH this input was given as or found in
+    arg: usize,
+}
+
+/// Calls `f` for every input in `paths`, walking directories with
+/// `walk_config`. Returns the number of directory entries which could not be
+/// read.
+fn for_each_target<F>(
+    paths: &[PathBuf],
+    walk_config: &WalkConfig,
+    with_path: bool,
+    mut f: F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Target) -> anyhow::Result<()>,
+{
+    let mut failed = 0;
+    for (arg, path) in paths.iter().enumerate() {
+        if path.is_dir() {
+            for entry in walk::files(path, walk_config)? {
+                match entry {
+                    Ok(path) => f(Target {
+                        path,
+                        with_path: true,
+                        arg,
+                    })?,
+                    Err(err) => {
+                        eprintln!("sp: {}", err);
+                        failed += 1;
+                    }
+                }
+            }
+        } else {
+            f(Target {
+                path: path.clone(),
+                with_path,
+                arg,
+            })?;
+        }
+    }
+    Ok(failed)
+}
+
+/// Searches `target` and prints its matches. Returns `false` if it could not
+/// be opened.
+fn search_target<W: Write>(
+    wrt: &mut Writer<W>,
+    matcher: &Matcher,
+    mmap: MmapChoice,
+    target: &Target,
+) -> anyhow::Result<bool> {
+    if target.path.as_os_str() == STDIN_PATH {
+        let label = target.with_path.then_some(Path::new(STDIN_LABEL));
+        print_stdin(wrt, matcher, label)?;
+        Ok(true)
+    } else {
+        print_file(wrt, &target.path, matcher, mmap, target.with_path)
+    }
+}
+
+/// Reads one pattern per line from `path`, or from stdin if `path` is -
+fn read_patterns(path: &Path) -> anyhow::Result<Vec<String>> {
+    let contents = if path.as_os_str() == STDIN_PATH {
+        let mut contents = Vec::new();
+        io::stdin()
+            .read_to_end(&mut contents)
+            .context("Could not read patterns from stdin")?;
+        contents
+    } else {
+        fs::read(path).with_context(|| format!("Could not read pattern file {:?}", path))?
+    };
+
+    ByteSlice::lines(contents.as_slice())
+        .map(|line| {
+            line.to_str()
+                .map(str::to_owned)
+                .with_context(|| format!("Pattern file {:?} is not valid UTF-8", path))
+        })
+        .collect()
+}
+
+/// Searches stdin and prints its matches, prefixed with `label` if given.
+fn print_stdin<W: Write>(wrt: &mut Writer<W>, matcher: &Matcher, label: Option<&Path>) -> Output {
+    let stdin = io::stdin();
+
+    let searcher = Searcher {
+        reader: stdin.lock(),
+        matcher,
+    };
+
+    search_into(searcher, wrt, &matcher.config, label).context("Could not search stdin")
+}
+
+/// Searches a single file and prints its matches, prefixed with `path` if
+/// `with_path` is set. The file is memory mapped as requested by `mmap`.
+/// Returns `false` if the file could not be opened.
+fn print_file<W: Write>(
+    wrt: &mut Writer<W>,
+    path: &Path,
+    matcher: &Matcher,
+    mmap: MmapChoice,
+    with_path: bool,
+) -> anyhow::Result<bool> {
+    let f = match File::open(path).with_context(|| format!("Could not read file {:?}", path)) {
+        Ok(f) => f,
+        Err(err) => {
+            eprintln!("sp: {:#}", err);
+            return Ok(false);
+        }
+    };
+
+    let label = with_path.then_some(path);
+    // A mapped file is searched in place, with the same line splitting as a
+    // read one
+    let searched = match mmap.map(&f) {
+        Some(map) => {
+            let searcher = Searcher {
+                reader: &map[..],
+                matcher,
+            };
+            search_into(searcher, wrt, &matcher.config, label)
+        }
+        None => {
+            let searcher = Searcher {
+                reader: BufReader::new(f),
+                matcher,
+            };
+            search_into(searcher, wrt, &matcher.config, label)
+        }
+    };
+    searched.with_context(|| format!("Could not search file {:?}", path))?;
+
+    Ok(true)
+}
+
+/// Passes the results of `searcher` to a sink for the requested output format
+fn search_into<R: BufRead, W: Write>(
+    searcher: Searcher<R>,
+    wrt: &mut Writer<W>,
+    config: &Config,
+    label: Option<&Path>,
+) -> io::Result<()> {
+    if config.json {
+        searcher.search_into(&mut wrt.json_sink(config, label))
+    } else {
+        searcher.search_into(&mut wrt.sink(config, label))
+    }
+}
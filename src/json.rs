@@ -0,0 +1,369 @@
+//! Prints search results as JSON Lines, i.e. one JSON object per line for
+//! every event: the begin and end of each input with matches, every matching
+//! or context line and a final summary.
+
+use crate::matcher::Config;
+use crate::results::LineKind;
+use crate::sink::{CountKind, Sink, SinkLine};
+use bstr::ByteVec;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str;
+
+/// Totals over all searched inputs, written as the final summary
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub searches: u64,
+    pub searches_with_match: u64,
+    pub matched_lines: u64,
+    pub matches: u64,
+}
+
+impl Stats {
+    /// Adds the totals of `other`, e.g. of inputs searched on another thread
+    pub const fn add(&mut self, other: &Self) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonSink<'w, W> {
+    wrt: &'w mut W,
+    stats: &'w mut Stats,
+    config: &'w Config,
+    path: Option<&'w Path>,
+    /// Whether the begin event of this input has been written
+    begun: bool,
+    /// Whether this input matches
+    matched: bool,
+    binary: bool,
+    matched_lines: u64,
+    matches: u64,
+}
+
+impl<'w, W: Write> JsonSink<'w, W> {
+    pub const fn new(
+        wrt: &'w mut W,
+        stats: &'w mut Stats,
+        config: &'w Config,
+        path: Option<&'w Path>,
+    ) -> Self {
+        Self {
+            wrt,
+            stats,
+            config,
+            path,
+            begun: false,
+            matched: false,
+            binary: false,
+            matched_lines: 0,
+            matches: 0,
+        }
+    }
+
+    fn path(&self) -> Value {
+        self.path
+            .map_or(Value::Null, |path| data(&Vec::from_path_lossy(path)))
+    }
+
+    /// Writes the begin event before the first line of this input
+    fn begin(&mut self) -> io::Result<()> {
+        if !self.begun {
+            self.begun = true;
+            let path = self.path();
+            write_event(self.wrt, "begin", json!({ "path": path }))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'w, W: Write> Sink for JsonSink<'w, W> {
+    fn line(&mut self, line: &SinkLine) -> io::Result<bool> {
+        self.begin()?;
+
+        let kind = match line.kind {
+            LineKind::Match => {
+                self.matched = true;
+                self.matched_lines += 1;
+                self.matches += line.matches.len() as u64;
+                "match"
+            }
+            LineKind::Context => "context",
+        };
+
+        let submatches: Vec<_> = line
+            .matches
+            .iter()
+            .map(|m| {
+                // Like the writer, never slice beyond the end of the line
+                let end = m.end.min(line.line.len());
+                let start = m.start.min(end);
+                json!({
+                    "match": data(&line.line[start..end]),
+                    "start": start,
+                    "end": end,
+                })
+            })
+            .collect();
+
+        let path = self.path();
+        let data = json!({
+            "path": path,
+            "lines": data(line.line),
+            "line_number": line.line_number,
+            "absolute_offset": line.byte_offset,
+            "submatches": submatches,
+        });
+        write_event(self.wrt, kind, data)?;
+        Ok(true)
+    }
+
+    fn binary_match(&mut self) -> io::Result<()> {
+        self.begin()?;
+        self.matched = true;
+        self.binary = true;
+        self.matched_lines += 1;
+        Ok(())
+    }
+
+    fn count(&mut self, count: u64, kind: CountKind) -> io::Result<()> {
+        match kind {
+            CountKind::Lines => self.stats.matched_lines += count,
+            CountKind::Matches => self.stats.matches += count,
+        }
+        if count > 0 {
+            self.stats.searches_with_match += 1;
+        }
+        let path = self.path();
+        write_event(self.wrt, "count", json!({ "path": path, "count": count }))
+    }
+
+    /// Only the listed inputs are reported, by their begin and end events
+    fn matched(&mut self, matched: bool) -> io::Result<()> {
+        self.matched = matched;
+        if matched != self.config.files_without_match {
+            self.begin()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.stats.searches += 1;
+        if self.matched {
+            self.stats.searches_with_match += 1;
+        }
+        if !self.begun {
+            return Ok(());
+        }
+        self.stats.matched_lines += self.matched_lines;
+        self.stats.matches += self.matches;
+
+        let path = self.path();
+        let data = json!({
+            "path": path,
+            "binary": self.binary,
+            "stats": {
+                "matched_lines": self.matched_lines,
+                "matches": self.matches,
+            },
+        });
+        write_event(self.wrt, "end", data)
+    }
+}
+
+/// Writes the summary event once all inputs have been searched
+pub fn write_summary<W: Write>(wrt: &mut W, stats: &Stats) -> io::Result<()> {
+    let data = json!({
+        "stats": {
+            "searches": stats.searches,
+            "searches_with_match": stats.searches_with_match,
+            "matched_lines": stats.matched_lines,
+            "matches": stats.matches,
+        },
+    });
+    write_event(wrt, "summary", data)
+}
+
+fn write_event<W: Write>(wrt: &mut W, kind: &str, data: Value) -> io::Result<()> {
+    serde_json::to_writer(&mut *wrt, &json!({ "type": kind, "data": data }))?;
+    writeln!(wrt)
+}
+
+/// Represents `bytes` as `{"text": ...}` if they are valid UTF-8 and as
+/// base64 encoded `{"bytes": ...}` otherwise
+fn data(bytes: &[u8]) -> Value {
+    str::from_utf8(bytes).map_or_else(
+        |_| json!({ "bytes": base64::encode(bytes) }),
+        |text| json!({ "text": text }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_summary, JsonSink, Stats};
+    use crate::matcher::MatcherBuilder;
+    use crate::search::Searcher;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    const LINES: &[u8] = b"a run\nb\n\xffrun run\n";
+
+    #[test]
+    fn json_lines() {
+        let expected = r#"{"data":{"path":{"text":"lines.txt"}},"type":"begin"}
+{"data":{"absolute_offset":0,"line_number":1,"lines":{"text":"a run\n"},"path":{"text":"lines.txt"},"submatches":[{"end":5,"match":{"text":"run"},"start":2}]},"type":"match"}
+{"data":{"absolute_offset":6,"line_number":2,"lines":{"text":"b\n"},"path":{"text":"lines.txt"},"submatches":[]},"type":"context"}
+{"data":{"absolute_offset":8,"line_number":3,"lines":{"bytes":"/3J1biBydW4K"},"path":{"text":"lines.txt"},"submatches":[{"end":4,"match":{"text":"run"},"start":1},{"end":8,"match":{"text":"run"},"start":5}]},"type":"match"}
+{"data":{"binary":false,"path":{"text":"lines.txt"},"stats":{"matched_lines":2,"matches":3}},"type":"end"}
+{"data":{"stats":{"matched_lines":2,"matches":3,"searches":1,"searches_with_match":1}},"type":"summary"}
+"#;
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(LINES),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Vec::new();
+        let mut stats = Stats::default();
+        let path = Path::new("lines.txt");
+        searcher
+            .search_into(&mut JsonSink::new(
+                &mut wrt,
+                &mut stats,
+                &matcher.config,
+                Some(path),
+            ))
+            .unwrap();
+        write_summary(&mut wrt, &stats).unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt).unwrap());
+    }
+
+    #[test]
+    fn json_count() {
+        let expected = r#"{"data":{"count":2,"path":null},"type":"count"}
+"#;
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new().count(true).build(pattern).unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(LINES),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Vec::new();
+        let mut stats = Stats::default();
+        searcher
+            .search_into(&mut JsonSink::new(
+                &mut wrt,
+                &mut stats,
+                &matcher.config,
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt).unwrap());
+        assert_eq!(stats.matched_lines, 2);
+    }
+
+    #[test]
+    fn json_count_matches() {
+        let expected = r#"{"data":{"count":3,"path":null},"type":"count"}
+{"data":{"stats":{"matched_lines":0,"matches":3,"searches":1,"searches_with_match":1}},"type":"summary"}
+"#;
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .count_matches(true)
+            .build(pattern.clone())
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(LINES),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Vec::new();
+        let mut stats = Stats::default();
+        searcher
+            .search_into(&mut JsonSink::new(
+                &mut wrt,
+                &mut stats,
+                &matcher.config,
+                None,
+            ))
+            .unwrap();
+        write_summary(&mut wrt, &stats).unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt).unwrap());
+
+        // Inverted selections have no matches, so their lines are counted
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .count_matches(true)
+            .invert(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(LINES),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Vec::new();
+        let mut stats = Stats::default();
+        searcher
+            .search_into(&mut JsonSink::new(
+                &mut wrt,
+                &mut stats,
+                &matcher.config,
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!((stats.matched_lines, stats.matches), (1, 0));
+    }
+
+    #[test]
+    fn json_files_without_match() {
+        let expected = r#"{"data":{"path":{"text":"b.txt"}},"type":"begin"}
+{"data":{"binary":false,"path":{"text":"b.txt"},"stats":{"matched_lines":0,"matches":0}},"type":"end"}
+{"data":{"stats":{"matched_lines":0,"matches":0,"searches":2,"searches_with_match":1}},"type":"summary"}
+"#;
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .files_without_match(true)
+            .build(pattern)
+            .unwrap();
+
+        let mut wrt = Vec::new();
+        let mut stats = Stats::default();
+        for (path, lines) in &[("a.txt", LINES), ("b.txt", &b"b\n"[..])] {
+            let searcher = Searcher {
+                reader: Cursor::new(lines),
+                matcher: &matcher,
+            };
+            let path = Some(Path::new(path));
+            searcher
+                .search_into(&mut JsonSink::new(
+                    &mut wrt,
+                    &mut stats,
+                    &matcher.config,
+                    path,
+                ))
+                .unwrap();
+        }
+        write_summary(&mut wrt, &stats).unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt).unwrap());
+    }
+}
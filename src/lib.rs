@@ -4,19 +4,31 @@
     missing_docs,
     // unreachable_pub
 )]
-#![forbid(unsafe_code)]
+// Only allowed for memory mapping files, see `mmap`
+#![deny(unsafe_code)]
 // #![warn(clippy::pedantic)]
 
 //! This library implements all functionalities provided by `sp`. It relies
 //! on the following crates:
 //!
 //! * `bstr`: string oriented methods for byte strings: similar to
-//! Unicode strings but *not guaranteed* to be valid UTF-8.
+//!   Unicode strings but *not guaranteed* to be valid UTF-8.
+//! * `regex`: regular expression matching on byte strings
+//! * `aho-corasick`: searching for many literal patterns at once
+//! * `caseless`: Unicode case folding for caseless matching
 //! * `anyhow`: convenient and idiomatic error handling
 //! * `structopt`: parsing command line arguments and many additional features
+//! * `ignore`: recursive directory traversal respecting ignore files
+//! * `memmap2`: searching large files through a memory map
+//! * `serde_json` and `base64`: JSON Lines output
 pub mod cli;
 pub(crate) mod ext;
+pub(crate) mod json;
 pub(crate) mod matcher;
+pub(crate) mod mmap;
+pub(crate) mod parallel;
 pub(crate) mod results;
 pub(crate) mod search;
+pub(crate) mod sink;
+pub(crate) mod walk;
 pub(crate) mod writer;
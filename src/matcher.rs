@@ -1,41 +1,101 @@
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind, StartKind};
+use regex::bytes::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::str::FromStr;
+
 /// Internal configuration of our cli which can only by modified by `MatcherBuilder`.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Config {
+    pub after_context: usize,
+    pub before_context: usize,
+    pub binary: BinaryDetection,
+    pub byte_offset: bool,
+    pub color: bool,
+    pub column: bool,
     pub count: bool,
+    pub count_matches: bool,
     pub ends_with: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
     pub ignore_case: bool,
+    pub invert: bool,
+    pub json: bool,
+    pub line_regexp: bool,
+    pub lossy: bool,
     pub max_count: Option<u64>,
     pub no_line_number: bool,
+    pub only_matching: bool,
+    pub regex: bool,
+    pub smart_case: bool,
     pub starts_with: bool,
     pub words: bool,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            count: false,
-            ends_with: false,
-            ignore_case: false,
-            max_count: None,
-            no_line_number: false,
-            starts_with: false,
-            words: false,
+impl Config {
+    /// Whether only the paths of (non-)matching inputs are printed
+    pub const fn lists_files(&self) -> bool {
+        self.files_with_matches || self.files_without_match
+    }
+}
+
+/// What to do with inputs which look like binary data, i.e. which contain a
+/// NUL byte in their first block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryDetection {
+    /// Stop at the first match and only report that the input matches
+    Quit,
+    /// Do not search the input at all
+    Skip,
+    /// Do not detect binary data and search the input like any other
+    #[default]
+    Text,
+}
+
+impl FromStr for BinaryDetection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quit" => Ok(Self::Quit),
+            "skip" => Ok(Self::Skip),
+            "text" => Ok(Self::Text),
+            _ => anyhow::bail!("Unknown binary policy {:?}", s),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Matcher {
-    pub pattern: String,
+    /// A line matches if any of these matches
+    pub patterns: Vec<String>,
     pub config: Config,
     pub matcher_type: MatcherType,
+    /// All `patterns` compiled into a single regex; only present for
+    /// `MatcherType::Regex` with at least one pattern
+    pub regex: Option<Regex>,
+    /// All `patterns` compiled into a single automaton; only present for
+    /// `MatcherType::Base`, `MaxCount`, `StartsWith` and `EndsWith`
+    pub literals: Option<AhoCorasick>,
+    /// All `patterns` as whole lines; only present for
+    /// `MatcherType::LineRegexp`
+    pub lines: Option<HashSet<Vec<u8>>>,
+}
+
+impl Matcher {
+    /// Whether lines have to be case folded before they are checked. A regex
+    /// takes care of caselessness itself
+    pub const fn fold_lines(&self) -> bool {
+        self.config.ignore_case && self.regex.is_none()
+    }
 }
 
 #[derive(Debug)]
 pub enum MatcherType {
     Base,
     EndsWith,
+    LineRegexp,
     MaxCount,
+    Regex,
     StartsEndsWith,
     StartsWith,
     Words,
@@ -52,7 +112,7 @@ impl Default for MatcherBuilder {
     }
 }
 
-impl<'a> MatcherBuilder {
+impl MatcherBuilder {
     /// Create a new Config builder with a default configuration.
     pub fn new() -> Self {
         Self {
@@ -60,82 +120,349 @@ impl<'a> MatcherBuilder {
         }
     }
 
+    /// Disabled (i.e. 0) by default
+    pub const fn after_context(&mut self, v: usize) -> &mut Self {
+        self.config.after_context = v;
+        self
+    }
+
+    /// Disabled (i.e. 0) by default
+    pub const fn before_context(&mut self, v: usize) -> &mut Self {
+        self.config.before_context = v;
+        self
+    }
+
+    /// `BinaryDetection::Text` (i.e. no detection) by default
+    pub const fn binary(&mut self, v: BinaryDetection) -> &mut Self {
+        self.config.binary = v;
+        self
+    }
+
     /// Disabled (i.e. false) by default
-    pub fn count(&mut self, v: bool) -> &mut Self {
+    pub const fn byte_offset(&mut self, v: bool) -> &mut Self {
+        self.config.byte_offset = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn color(&mut self, v: bool) -> &mut Self {
+        self.config.color = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn column(&mut self, v: bool) -> &mut Self {
+        self.config.column = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn count(&mut self, v: bool) -> &mut Self {
         self.config.count = v;
         self
     }
 
+    /// Disabled (i.e. false) by default. Only used together with `count`
+    pub const fn count_matches(&mut self, v: bool) -> &mut Self {
+        self.config.count_matches = v;
+        self
+    }
+
     /// Disabled (i.e. false) by default
-    pub fn ends_with(&mut self, v: bool) -> &mut Self {
+    pub const fn ends_with(&mut self, v: bool) -> &mut Self {
         self.config.ends_with = v;
         self
     }
 
+    /// Disabled (i.e. false) by default. Overrides `count`
+    pub const fn files_with_matches(&mut self, v: bool) -> &mut Self {
+        self.config.files_with_matches = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default. Overrides `count`
+    pub const fn files_without_match(&mut self, v: bool) -> &mut Self {
+        self.config.files_without_match = v;
+        self
+    }
+
     /// Disabled (i.e. false) by default
-    pub fn ignore_case(&mut self, v: bool) -> &mut Self {
+    pub const fn ignore_case(&mut self, v: bool) -> &mut Self {
         self.config.ignore_case = v;
         self
     }
 
+    /// Disabled (i.e. false) by default
+    pub const fn invert(&mut self, v: bool) -> &mut Self {
+        self.config.invert = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn json(&mut self, v: bool) -> &mut Self {
+        self.config.json = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn line_regexp(&mut self, v: bool) -> &mut Self {
+        self.config.line_regexp = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn lossy(&mut self, v: bool) -> &mut Self {
+        self.config.lossy = v;
+        self
+    }
+
     /// Disabled (i.e. None) by default
-    pub fn max_count(&mut self, v: Option<u64>) -> &mut Self {
+    pub const fn max_count(&mut self, v: Option<u64>) -> &mut Self {
         self.config.max_count = v;
         self
     }
 
     /// Disabled (i.e. false) by default
-    pub fn no_line_number(&mut self, v: bool) -> &mut Self {
+    pub const fn no_line_number(&mut self, v: bool) -> &mut Self {
         self.config.no_line_number = v;
         self
     }
 
     /// Disabled (i.e. false) by default
-    pub fn starts_with(&mut self, v: bool) -> &mut Self {
+    pub const fn only_matching(&mut self, v: bool) -> &mut Self {
+        self.config.only_matching = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn regex(&mut self, v: bool) -> &mut Self {
+        self.config.regex = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default. Overridden by `ignore_case`
+    pub const fn smart_case(&mut self, v: bool) -> &mut Self {
+        self.config.smart_case = v;
+        self
+    }
+
+    /// Disabled (i.e. false) by default
+    pub const fn starts_with(&mut self, v: bool) -> &mut Self {
         self.config.starts_with = v;
         self
     }
 
     /// Disabled (i.e. false) by default
-    pub fn words(&mut self, v: bool) -> &mut Self {
+    pub const fn words(&mut self, v: bool) -> &mut Self {
         self.config.words = v;
         self
     }
 
-    /// Build `MatcherBuilder`
-    pub fn build(&self, mut pattern: String) -> Matcher {
-        if self.config.ignore_case {
-            pattern = pattern.to_lowercase();
+    /// Build `MatcherBuilder` for a single pattern
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `regex` is enabled and `pattern` is not a valid
+    /// regular expression
+    #[cfg(test)]
+    pub fn build(&self, pattern: String) -> anyhow::Result<Matcher> {
+        self.build_many(vec![pattern])
+    }
+
+    /// Build `MatcherBuilder` for lines matching any of `patterns`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `regex` is enabled and one of `patterns` is not a
+    /// valid regular expression, or if `patterns` are too large to be
+    /// searched for at once
+    pub fn build_many(&self, mut patterns: Vec<String>) -> anyhow::Result<Matcher> {
+        // With smart case, any uppercase character makes the search case
+        // sensitive
+        let ignore_case = self.config.ignore_case
+            || (self.config.smart_case
+                && !patterns
+                    .iter()
+                    .any(|pattern| has_uppercase(pattern, self.config.regex)));
+
+        // A regex handles caselessness itself; folding it would change
+        // the meaning of escapes like `\W` or `\S`
+        if ignore_case && !self.config.regex {
+            for pattern in &mut patterns {
+                *pattern = caseless::default_case_fold_str(pattern);
+            }
         }
 
         let config = Config {
-            count: self.config.count,
+            after_context: self.config.after_context,
+            before_context: self.config.before_context,
+            binary: self.config.binary,
+            byte_offset: self.config.byte_offset,
+            color: self.config.color,
+            column: self.config.column,
+            // Like in grep, listing files takes precedence over counting
+            count: self.config.count && !self.config.lists_files(),
+            count_matches: self.config.count_matches,
             ends_with: self.config.ends_with,
-            ignore_case: self.config.ignore_case,
+            files_with_matches: self.config.files_with_matches,
+            files_without_match: self.config.files_without_match,
+            ignore_case,
+            invert: self.config.invert,
+            json: self.config.json,
+            line_regexp: self.config.line_regexp,
+            lossy: self.config.lossy,
             max_count: self.config.max_count,
             no_line_number: self.config.no_line_number,
+            only_matching: self.config.only_matching,
+            regex: self.config.regex,
+            smart_case: self.config.smart_case,
             starts_with: self.config.starts_with,
             words: self.config.words,
         };
 
         let matcher_type = match (
+            self.config.regex,
+            self.config.line_regexp,
             self.config.words,
             self.config.ends_with,
             self.config.starts_with,
             self.config.max_count.is_some(),
         ) {
-            (true, _, _, _) => MatcherType::Words,
-            (false, true, true, _) => MatcherType::StartsEndsWith,
-            (false, true, false, _) => MatcherType::EndsWith,
-            (false, false, true, _) => MatcherType::StartsWith,
-            (false, false, false, true) => MatcherType::MaxCount,
-            (false, false, false, false) => MatcherType::Base,
+            (true, _, _, _, _, _) => MatcherType::Regex,
+            (false, true, _, _, _, _) => MatcherType::LineRegexp,
+            (false, false, true, _, _, _) => MatcherType::Words,
+            (false, false, false, true, true, _) => MatcherType::StartsEndsWith,
+            (false, false, false, true, false, _) => MatcherType::EndsWith,
+            (false, false, false, false, true, _) => MatcherType::StartsWith,
+            (false, false, false, false, false, true) => MatcherType::MaxCount,
+            (false, false, false, false, false, false) => MatcherType::Base,
+        };
+
+        let regex = if self.config.regex && !patterns.is_empty() {
+            // Each pattern is checked on its own first for a more precise error
+            for pattern in &patterns {
+                RegexBuilder::new(pattern).build()?;
+            }
+            let alternation = patterns
+                .iter()
+                .map(|pattern| format!("(?:{})", pattern))
+                .collect::<Vec<_>>()
+                .join("|");
+            // With --line-regexp, a match has to span the whole line
+            let alternation = if self.config.line_regexp {
+                format!("^(?:{})$", alternation)
+            } else {
+                alternation
+            };
+            let regex = RegexBuilder::new(&alternation)
+                .case_insensitive(ignore_case)
+                .build()?;
+            Some(regex)
+        } else {
+            None
+        };
+
+        // Each line is scanned once, however many patterns there are
+        let literals = match matcher_type {
+            MatcherType::Base | MatcherType::MaxCount => Some(
+                AhoCorasickBuilder::new()
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(&patterns)?,
+            ),
+            // Only matches at the start of the searched field
+            MatcherType::StartsWith => Some(
+                AhoCorasickBuilder::new()
+                    .start_kind(StartKind::Anchored)
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(&patterns)?,
+            ),
+            // Overlapping matches are needed to find all those ending a field
+            MatcherType::EndsWith => Some(AhoCorasick::new(&patterns)?),
+            _ => None,
+        };
+
+        // Each line is looked up once, however many patterns there are
+        let lines = match matcher_type {
+            MatcherType::LineRegexp => Some(
+                patterns
+                    .iter()
+                    .map(|pattern| pattern.as_bytes().to_vec())
+                    .collect(),
+            ),
+            _ => None,
         };
 
-        Matcher {
-            pattern,
+        Ok(Matcher {
+            patterns,
             config,
             matcher_type,
+            regex,
+            literals,
+            lines,
+        })
+    }
+}
+
+/// Whether `pattern` contains an uppercase character. Escape sequences of a
+/// regex, like `\W`, `\x{4A}` or `\p{Lu}`, do not count
+fn has_uppercase(pattern: &str, regex: bool) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' && regex {
+            let escaped = chars.next();
+            // Skip bracketed arguments such as in `\p{Greek}` or `\u{1F600}`
+            if escaped.is_some_and(char::is_alphabetic) && chars.as_str().starts_with('{') {
+                chars.by_ref().find(|&c| c == '}');
+            } else if matches!(escaped, Some('p' | 'P')) {
+                // Single letter classes such as `\pL`
+                chars.next();
+            }
+        } else if c.is_uppercase() {
+            return true;
         }
     }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_uppercase, MatcherBuilder};
+
+    #[test]
+    fn uppercase_characters() {
+        assert!(!has_uppercase("gain", false));
+        assert!(has_uppercase("aΓain", false));
+        assert!(!has_uppercase("aγain", false));
+        assert!(has_uppercase("\\W", false));
+        assert!(!has_uppercase("\\W+\\S", true));
+        assert!(!has_uppercase("\\p{Greek}\\pL\\x{4A}", true));
+        assert!(has_uppercase("\\pLA", true));
+        assert!(has_uppercase("\\\\W", true));
+    }
+
+    #[test]
+    fn smart_case() {
+        let patterns = vec!["gain".to_owned(), "aγain".to_owned()];
+        let matcher = MatcherBuilder::new()
+            .smart_case(true)
+            .build_many(patterns)
+            .unwrap();
+        assert!(matcher.config.ignore_case);
+
+        let patterns = vec!["gain".to_owned(), "AΓain".to_owned()];
+        let matcher = MatcherBuilder::new()
+            .smart_case(true)
+            .build_many(patterns)
+            .unwrap();
+        assert!(!matcher.config.ignore_case);
+        assert_eq!(matcher.patterns, vec!["gain", "AΓain"]);
+
+        let matcher = MatcherBuilder::new()
+            .ignore_case(true)
+            .smart_case(true)
+            .build("AΓain".to_owned())
+            .unwrap();
+        assert!(matcher.config.ignore_case);
+        assert_eq!(matcher.patterns, vec!["aγain"]);
+    }
 }
@@ -0,0 +1,68 @@
+//! Decides whether a file is searched through a memory map instead of being
+//! read block by block.
+
+use memmap2::Mmap;
+use std::fs::File;
+
+/// Files at least this large are memory mapped by default. For smaller files,
+/// setting up the map costs more than the read calls it saves
+const MIN_LEN: u64 = 1024 * 1024;
+
+/// Whether files are memory mapped, as requested by --mmap and --no-mmap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmapChoice {
+    /// Map regular files which are large enough
+    Auto,
+    /// Map every file which can be mapped
+    Always,
+    /// Never map files
+    Never,
+}
+
+impl MmapChoice {
+    /// Maps `file` if requested. Returns `None` if the file should be read
+    /// instead, which includes files which cannot be mapped
+    pub fn map(self, file: &File) -> Option<Mmap> {
+        let wanted = match self {
+            Self::Auto => file
+                .metadata()
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() >= MIN_LEN),
+            Self::Always => true,
+            Self::Never => false,
+        };
+        if !wanted {
+            return None;
+        }
+        // SAFETY: the map is only read from. If another process truncates
+        // the file while it is searched, reading may fail with SIGBUS; like
+        // other search tools, we accept this for the speedup on large files
+        #[allow(unsafe_code)]
+        let mmap = unsafe { Mmap::map(file) };
+        mmap.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MmapChoice, MIN_LEN};
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn map_large_files() {
+        let mut small = tempfile::NamedTempFile::new().unwrap();
+        writeln!(small, "run").unwrap();
+        let file = File::open(small.path()).unwrap();
+
+        assert!(MmapChoice::Auto.map(&file).is_none());
+        assert!(MmapChoice::Never.map(&file).is_none());
+        assert_eq!(&MmapChoice::Always.map(&file).unwrap()[..], b"run\n");
+
+        let large = tempfile::NamedTempFile::new().unwrap();
+        large.as_file().set_len(MIN_LEN).unwrap();
+        let file = File::open(large.path()).unwrap();
+
+        assert_eq!(MmapChoice::Auto.map(&file).unwrap().len() as u64, MIN_LEN);
+        assert!(MmapChoice::Never.map(&file).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+//! Searches several inputs at once on a pool of worker threads. The results
+//! of each input are buffered and printed at once, so that lines of different
+//! inputs are never interleaved. Each job belongs to a group; groups are
+//! printed in the order in which they were submitted, while the jobs within
+//! a group are printed as soon as they are done.
+
+use crate::writer::Writer;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::Scope;
+
+/// Whether an input could be searched and its buffered results
+type Done = (anyhow::Result<bool>, Writer<Vec<u8>>);
+
+#[derive(Debug)]
+pub struct Pool<J> {
+    /// Each job together with its group
+    jobs: Sender<(usize, J)>,
+    results: Receiver<(usize, Done)>,
+    /// Put each job in a group of its own, so that all results are printed
+    /// in the order in which jobs were submitted
+    sorted: bool,
+    submitted: usize,
+    received: usize,
+    /// Number of jobs of each group whose results have not been printed yet
+    unprinted: BTreeMap<usize, usize>,
+    /// Results waiting for those of earlier groups
+    pending: BTreeMap<usize, Vec<Done>>,
+    /// Number of inputs which could not be searched
+    failed: usize,
+}
+
+impl<J: Send> Pool<J> {
+    /// Spawns `threads` workers within `scope`, each calling `search` for one
+    /// job at a time
+    pub fn new<'scope, 'env, F>(
+        scope: &'scope Scope<'scope, 'env>,
+        threads: usize,
+        sorted: bool,
+        search: &'scope F,
+    ) -> Self
+    where
+        J: 'scope,
+        F: Fn(&mut Writer<Vec<u8>>, J) -> anyhow::Result<bool> + Sync,
+    {
+        let (jobs, job_receiver) = mpsc::channel::<(usize, J)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for _ in 0..threads {
+            let (job_receiver, result_sender) = (Arc::clone(&job_receiver), result_sender.clone());
+            scope.spawn(move || loop {
+                // The lock is only held while waiting for the next job
+                let job = match job_receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    // Another worker panicked
+                    Err(_) => break,
+                };
+                let (group, job) = match job {
+                    Ok(job) => job,
+                    // All jobs are done
+                    Err(_) => break,
+                };
+                let mut wrt = Writer::buffer();
+                let searched = search(&mut wrt, job);
+                if result_sender.send((group, (searched, wrt))).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Self {
+            jobs,
+            results,
+            sorted,
+            submitted: 0,
+            received: 0,
+            unprinted: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            failed: 0,
+        }
+    }
+
+    /// Queues `job` as part of `group` and prints the results of all jobs
+    /// done so far to `wrt`. Groups have to be submitted in ascending order
+    pub fn submit<W: Write>(
+        &mut self,
+        job: J,
+        group: usize,
+        wrt: &mut Writer<W>,
+    ) -> anyhow::Result<()> {
+        let group = if self.sorted { self.submitted } else { group };
+        self.jobs
+            .send((group, job))
+            .map_err(|_| anyhow::anyhow!("All search threads have stopped"))?;
+        self.submitted += 1;
+        *self.unprinted.entry(group).or_insert(0) += 1;
+        while let Ok(result) = self.results.try_recv() {
+            self.done(result, wrt)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for all jobs and prints their results to `wrt`. Returns the
+    /// number of inputs which could not be searched
+    pub fn finish<W: Write>(mut self, wrt: &mut Writer<W>) -> anyhow::Result<usize> {
+        while self.received < self.submitted {
+            let result = self.results.recv()?;
+            self.done(result, wrt)?;
+        }
+        Ok(self.failed)
+    }
+
+    fn done<W: Write>(
+        &mut self,
+        (group, done): (usize, Done),
+        wrt: &mut Writer<W>,
+    ) -> anyhow::Result<()> {
+        self.received += 1;
+        self.pending.entry(group).or_default().push(done);
+        // Only the earliest group with unprinted jobs can be printed. Once
+        // it is complete, the next one may already be done
+        while let Some(&group) = self.unprinted.keys().next() {
+            let results = match self.pending.remove(&group) {
+                Some(results) => results,
+                None => break,
+            };
+            let left = self.unprinted[&group] - results.len();
+            if left == 0 {
+                self.unprinted.remove(&group);
+            } else {
+                self.unprinted.insert(group, left);
+            }
+            for done in results {
+                self.print(done, wrt)?;
+            }
+            if left > 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn print<W: Write>(
+        &mut self,
+        (searched, buf): Done,
+        wrt: &mut Writer<W>,
+    ) -> anyhow::Result<()> {
+        let searched = searched?;
+        wrt.append(&buf)?;
+        self.failed += usize::from(!searched);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pool;
+    use crate::writer::Writer;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn sorted_results() {
+        // Earlier jobs take longer, so they finish last
+        let search = |wrt: &mut Writer<Vec<u8>>, job: u64| {
+            thread::sleep(Duration::from_millis(10 * (5 - job)));
+            writeln!(wrt.wrt, "{}a", job)?;
+            writeln!(wrt.wrt, "{}b", job)?;
+            Ok(job != 3)
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        let failed = thread::scope(|scope| {
+            let mut pool = Pool::new(scope, 4, true, &search);
+            for job in 0..5 {
+                pool.submit(job, 0, &mut wrt).unwrap();
+            }
+            pool.finish(&mut wrt).unwrap()
+        });
+
+        assert_eq!(failed, 1);
+        let expected = "0a\n0b\n1a\n1b\n2a\n2b\n3a\n3b\n4a\n4b\n";
+        assert_eq!(String::from_utf8(wrt.wrt).unwrap(), expected);
+    }
+
+    #[test]
+    fn unsorted_results_not_interleaved() {
+        let search = |wrt: &mut Writer<Vec<u8>>, job: u64| {
+            for _ in 0..100 {
+                writeln!(wrt.wrt, "{}", job)?;
+            }
+            Ok(true)
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        thread::scope(|scope| {
+            let mut pool = Pool::new(scope, 4, false, &search);
+            for job in 0..20 {
+                pool.submit(job, 0, &mut wrt).unwrap();
+            }
+            pool.finish(&mut wrt).unwrap()
+        });
+
+        let output = String::from_utf8(wrt.wrt).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2000);
+        for chunk in lines.chunks(100) {
+            assert!(chunk.iter().all(|line| *line == chunk[0]));
+        }
+    }
+
+    #[test]
+    fn groups_in_order() {
+        // The only job of the first group takes longest
+        let search = |wrt: &mut Writer<Vec<u8>>, job: u64| {
+            thread::sleep(Duration::from_millis(if job == 0 { 50 } else { 0 }));
+            writeln!(wrt.wrt, "{}", job)?;
+            Ok(true)
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        thread::scope(|scope| {
+            let mut pool = Pool::new(scope, 4, false, &search);
+            pool.submit(0, 0, &mut wrt).unwrap();
+            for job in 1..5 {
+                pool.submit(job, 1, &mut wrt).unwrap();
+            }
+            pool.submit(5, 2, &mut wrt).unwrap();
+            pool.finish(&mut wrt).unwrap()
+        });
+
+        let output = String::from_utf8(wrt.wrt).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], "0");
+        let mut second: Vec<_> = lines[1..5].to_vec();
+        second.sort_unstable();
+        assert_eq!(second, vec!["1", "2", "3", "4"]);
+        assert_eq!(lines[5], "5");
+    }
+}
@@ -1,217 +1,181 @@
 use crate::ext::ByteSliceExt;
-use bstr::{BString, ByteSlice};
+use aho_corasick::{AhoCorasick, Anchored, Input};
+use bstr::ByteSlice;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
 use std::str;
 
-#[derive(Debug, PartialEq)]
-pub enum LineNumbers {
-    None,
-    Some(Vec<u64>),
+/// Distinguishes matching lines from the context surrounding them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    Match,
+    Context,
 }
 
-impl Default for LineNumbers {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
-#[derive(Debug, Default, PartialEq)]
-pub struct SearchResult {
-    pub matches: Vec<BString>,
-    pub line_numbers: LineNumbers,
-}
-
-#[derive(Debug, PartialEq)]
-pub enum GenInnerResult {
-    Search(SearchResult),
-    Count(CountResult),
-}
-
-pub type GenResult = Result<GenInnerResult, std::io::Error>;
-
 #[derive(Debug, Default)]
-pub struct SearchInnerResult {
-    pub matches: Vec<BString>,
-    pub line_numbers: Vec<u64>,
-}
-
-#[derive(Debug, Default, PartialEq)]
 pub struct CountResult {
     pub count: u64,
 }
 
-pub trait Upcast {
-    /// Note that even though this returns a `GenResult`, it cannot fail
-    fn upcast(self) -> GenResult;
-}
-
-impl Upcast for SearchInnerResult {
-    fn upcast(self) -> GenResult {
-        let match_result = if self.line_numbers.is_empty() {
-            SearchResult {
-                matches: self.matches,
-                line_numbers: LineNumbers::None,
-            }
-        } else {
-            SearchResult {
-                matches: self.matches,
-                line_numbers: LineNumbers::Some(self.line_numbers),
-            }
-        };
-
-        Ok(GenInnerResult::Search(match_result))
-    }
-}
-
-impl Upcast for CountResult {
-    fn upcast(self) -> GenResult {
-        Ok(GenInnerResult::Count(self))
-    }
-}
-
 impl CountResult {
-    pub fn check_and_add<F>(&mut self, pattern: &[u8], line: &[u8], check: F)
+    /// Adds one for a selected line or, if `occurrences` is set, the number
+    /// of matches in it. Inverted selections never contain matches, so each
+    /// of their lines adds one. Returns whether the line was selected
+    pub fn check_and_add<F>(
+        &mut self,
+        line: &[u8],
+        matches: &mut Vec<Range<usize>>,
+        occurrences: bool,
+        check: F,
+    ) -> bool
     where
-        F: Fn(&[u8], &[u8]) -> bool,
+        F: Fn(&[u8], &mut Vec<Range<usize>>) -> bool,
     {
-        if check(line, pattern) {
-            self.count += 1;
+        matches.clear();
+        let selected = check(line, matches);
+        if selected {
+            self.count += if occurrences && !matches.is_empty() {
+                matches.len() as u64
+            } else {
+                1
+            };
         }
+        selected
     }
 }
 
-impl SearchInnerResult {
-    pub fn check_and_store<F>(&mut self, pattern: &[u8], line_number: u64, line: &[u8], check: F)
-    where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line, pattern) {
-            self.matches.push(line.trim_terminator());
-            self.line_numbers.push(line_number);
-        }
-    }
-
-    pub fn check_and_store_nln<F>(&mut self, pattern: &[u8], line: &[u8], check: F)
-    where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line, pattern) {
-            self.matches.push(line.trim_terminator());
+/// Wraps `check` such that it selects lines matching any of `patterns`.
+/// Matches of different patterns are sorted and never overlap
+pub fn any_pattern<'p, F>(
+    check: F,
+    patterns: &'p [String],
+) -> impl for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool + 'p
+where
+    F: for<'r, 's, 'm> Fn(&'r [u8], &'s [u8], &'m mut Vec<Range<usize>>) -> bool + 'p,
+{
+    move |line, matches| {
+        let mut matched = false;
+        for pattern in patterns {
+            matched |= check(line, pattern.as_bytes(), matches);
         }
-    }
-
-    pub fn check_and_store_nln_max_count<F>(
-        &mut self,
-        pattern: &[u8],
-        line: &[u8],
-        matches_left: &mut u64,
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line, pattern) {
-            *matches_left -= 1;
-            self.matches.push(line.trim_terminator());
+        if patterns.len() > 1 {
+            // Prefer the longest of several matches starting at the same byte
+            matches.sort_unstable_by_key(|m| (m.start, Reverse(m.end)));
+            let mut end = 0;
+            matches.retain(|m| {
+                let keep = m.start >= end;
+                if keep {
+                    end = m.end;
+                }
+                keep
+            });
         }
+        matched
     }
+}
 
-    pub fn check_and_store_separate<F>(
-        &mut self,
-        pattern: &[u8],
-        line_number: u64,
-        line_check: &[u8],
-        line_store: &[u8],
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line_check, pattern) {
-            self.matches.push(line_store.trim_terminator());
-            self.line_numbers.push(line_number);
-        }
-    }
+/// Wraps `check` such that it selects lines matching any of the patterns
+/// compiled into `literals`, e.g. an automaton or a set of whole lines
+pub fn any_literal<'l, L, F>(
+    check: F,
+    literals: &'l L,
+) -> impl for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool + 'l
+where
+    F: for<'r, 'm> Fn(&'r [u8], &L, &'m mut Vec<Range<usize>>) -> bool + 'l,
+{
+    move |line, matches| check(line, literals, matches)
+}
 
-    pub fn check_and_store_separate_nln<F>(
-        &mut self,
-        pattern: &[u8],
-        line_check: &[u8],
-        line_store: &[u8],
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line_check, pattern) {
-            self.matches.push(line_store.trim_terminator());
+/// Wraps `check` such that it selects non-matching lines if `invert` is set.
+/// Selected non-matching lines never contain any matches
+pub fn invert_if<F>(
+    check: F,
+    invert: bool,
+) -> impl for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool
+where
+    F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+{
+    move |line, matches| {
+        let matched = check(line, matches);
+        if invert {
+            matches.clear();
         }
+        matched != invert
     }
+}
 
-    pub fn check_and_store_separate_nln_max_count<F>(
-        &mut self,
-        pattern: &[u8],
-        line_check: &[u8],
-        line_store: &[u8],
-        matches_left: &mut u64,
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line_check, pattern) {
-            *matches_left -= 1;
-            self.matches.push(line_store.trim_terminator());
-        }
-    }
+// All check functions push the byte range of every match in `line` into
+// `matches` and return whether there was at least one
 
-    pub fn check_and_store_max_count<F>(
-        &mut self,
-        pattern: &[u8],
-        line_number: u64,
-        line: &[u8],
-        matches_left: &mut u64,
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line, pattern) {
-            *matches_left -= 1;
-            self.matches.push(line.trim_terminator());
-            self.line_numbers.push(line_number);
-        }
-    }
+// Unlike the other check functions, these get all patterns compiled into
+// `literals` and are wrapped by `any_literal` instead of `any_pattern`
 
-    pub fn check_and_store_separate_max_count<F>(
-        &mut self,
-        pattern: &[u8],
-        line_number: u64,
-        line_check: &[u8],
-        line_store: &[u8],
-        matches_left: &mut u64,
-        check: F,
-    ) where
-        F: Fn(&[u8], &[u8]) -> bool,
-    {
-        if check(line_check, pattern) {
-            *matches_left -= 1;
-            self.matches.push(line_store.trim_terminator());
-            self.line_numbers.push(line_number);
-        }
-    }
+/// Expects `literals` to support anchored searches and to prefer the longest
+/// match
+pub fn check_starts_with(
+    line: &[u8],
+    literals: &AhoCorasick,
+    matches: &mut Vec<Range<usize>>,
+) -> bool {
+    matches.extend(field_indices(line).filter_map(|(start, field)| {
+        let field = Input::new(field).anchored(Anchored::Yes);
+        literals.find(field).map(|m| start..start + m.end())
+    }));
+    !matches.is_empty()
 }
 
-pub fn check_starts_with(line: &[u8], pattern: &[u8]) -> bool {
-    line.fields().any(|word| word.starts_with_str(pattern))
+/// Expects `literals` to support overlapping matches. The longest pattern
+/// ending a field is reported
+pub fn check_ends_with(
+    line: &[u8],
+    literals: &AhoCorasick,
+    matches: &mut Vec<Range<usize>>,
+) -> bool {
+    matches.extend(field_indices(line).filter_map(|(start, field)| {
+        literals
+            .find_overlapping_iter(field)
+            .filter(|m| m.end() == field.len())
+            .map(|m| m.start())
+            .min()
+            .map(|first| start + first..start + field.len())
+    }));
+    !matches.is_empty()
 }
 
-pub fn check_ends_with(line: &[u8], pattern: &[u8]) -> bool {
-    line.fields().any(|word| word.ends_with_str(pattern))
+/// Expects `lines` to hold all patterns. The whole line without its
+/// terminator is reported as a match
+pub fn check_line(line: &[u8], lines: &HashSet<Vec<u8>>, matches: &mut Vec<Range<usize>>) -> bool {
+    let line = line.without_terminator();
+    if lines.contains(line) {
+        matches.push(0..line.len());
+    }
+    !matches.is_empty()
 }
 
-pub fn check_starts_ends_with(line: &[u8], pattern: &[u8]) -> bool {
-    line.fields()
-        .any(|word| word.starts_with_str(pattern) && word.ends_with_str(pattern))
+/// The whole field is reported as a match
+pub fn check_starts_ends_with(
+    line: &[u8],
+    pattern: &[u8],
+    matches: &mut Vec<Range<usize>>,
+) -> bool {
+    matches.extend(
+        field_indices(line)
+            .filter(|(_, field)| field.starts_with_str(pattern) && field.ends_with_str(pattern))
+            .map(|(start, field)| start..start + field.len()),
+    );
+    !matches.is_empty()
 }
 
-/// Only used by `base` and `max_count` modules
-pub fn check_contains(line: &[u8], pattern: &[u8]) -> bool {
-    line.contains_str(pattern)
+/// Only used by `base` and `max_count` modules. Expects `literals` to prefer
+/// the longest match
+pub fn check_contains(
+    line: &[u8],
+    literals: &AhoCorasick,
+    matches: &mut Vec<Range<usize>>,
+) -> bool {
+    matches.extend(literals.find_iter(line).map(|m| m.start()..m.end()));
+    !matches.is_empty()
 }
 
 // TODO: Would be nice (also for performance reasons in some cases, although very
@@ -223,10 +187,141 @@ pub fn check_contains(line: &[u8], pattern: &[u8]) -> bool {
 // Unfortunately, this does not work as it's not possible to have something like
 // nested trait bounds. Currently the only way to achieve it in our design would
 // be to replicate the `GenSearch` trait and replace `&[u8]` by `&str`
-pub fn check_words(line: &[u8], pattern: &[u8]) -> bool {
+pub fn check_words(line: &[u8], pattern: &[u8], matches: &mut Vec<Range<usize>>) -> bool {
     let pattern_utf8 =
         str::from_utf8(pattern).expect("Should never panic: pattern is always UTF-8");
-    line.trim_terminator()
-        .words()
-        .any(|word| word == pattern_utf8)
+    matches.extend(
+        line.without_terminator()
+            .word_indices()
+            .filter(|(_, _, word)| *word == pattern_utf8)
+            .map(|(start, end, _)| start..end),
+    );
+    !matches.is_empty()
+}
+
+/// Like `fields`, but also yields the byte offset of each field in `line`
+fn field_indices(line: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let base = line.as_ptr() as usize;
+    line.fields()
+        .map(move |field| (field.as_ptr() as usize - base, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{any_literal, any_pattern, check_line, check_words, invert_if};
+    use super::{check_contains, check_ends_with, check_starts_ends_with, check_starts_with};
+    use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind, StartKind};
+    use std::collections::HashSet;
+
+    const LINE: &[u8] = b"gain again, regain\tgain\n";
+
+    fn leftmost_longest(patterns: &[&str], anchored: bool) -> AhoCorasick {
+        let start_kind = if anchored {
+            StartKind::Anchored
+        } else {
+            StartKind::Unanchored
+        };
+        AhoCorasickBuilder::new()
+            .start_kind(start_kind)
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(patterns)
+            .unwrap()
+    }
+
+    #[test]
+    fn match_ranges() {
+        let mut matches = Vec::new();
+
+        assert!(check_contains(
+            LINE,
+            &leftmost_longest(&["gain"], false),
+            &mut matches
+        ));
+        assert_eq!(matches, vec![0..4, 6..10, 14..18, 19..23]);
+
+        matches.clear();
+        assert!(check_starts_with(
+            LINE,
+            &leftmost_longest(&["ga"], true),
+            &mut matches
+        ));
+        assert_eq!(matches, vec![0..2, 19..21]);
+
+        matches.clear();
+        assert!(check_ends_with(
+            LINE,
+            &AhoCorasick::new(["ain"]).unwrap(),
+            &mut matches
+        ));
+        assert_eq!(matches, vec![1..4, 15..18, 20..23]);
+
+        matches.clear();
+        assert!(check_starts_ends_with(LINE, b"gain", &mut matches));
+        assert_eq!(matches, vec![0..4, 19..23]);
+
+        let lines: HashSet<_> = vec![b"gain".to_vec(), b"again".to_vec()]
+            .into_iter()
+            .collect();
+        matches.clear();
+        assert!(check_line(b"gain\r\n", &lines, &mut matches));
+        assert_eq!(matches, vec![0..4]);
+
+        matches.clear();
+        assert!(!check_line(LINE, &lines, &mut matches));
+        assert!(matches.is_empty());
+
+        matches.clear();
+        assert!(check_words(LINE, b"again", &mut matches));
+        assert_eq!(matches, vec![5..10]);
+    }
+
+    #[test]
+    fn inverted_match_ranges() {
+        let mut matches = Vec::new();
+        let literals = leftmost_longest(&["gain"], false);
+        let check = invert_if(any_literal(check_contains, &literals), true);
+
+        assert!(!check(LINE, &mut matches));
+        assert!(matches.is_empty());
+        assert!(check(b"run", &mut matches));
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn any_literal_match_ranges() {
+        let mut matches = Vec::new();
+        let literals = leftmost_longest(&["again", "ga", "gain"], false);
+        let check = any_literal(check_contains, &literals);
+
+        assert!(check(LINE, &mut matches));
+        assert_eq!(matches, vec![0..4, 5..10, 14..18, 19..23]);
+
+        matches.clear();
+        assert!(!check(b"run", &mut matches));
+        assert!(matches.is_empty());
+
+        let literals = leftmost_longest(&["a", "ga", "re"], true);
+        matches.clear();
+        assert!(check_starts_with(LINE, &literals, &mut matches));
+        assert_eq!(matches, vec![0..2, 5..6, 12..14, 19..21]);
+
+        let literals = AhoCorasick::new(["n", "ain,", "gain"]).unwrap();
+        matches.clear();
+        assert!(check_ends_with(LINE, &literals, &mut matches));
+        assert_eq!(matches, vec![0..4, 7..11, 14..18, 19..23]);
+    }
+
+    #[test]
+    fn any_pattern_match_ranges() {
+        let mut matches = Vec::new();
+        let patterns = vec!["gain".to_owned(), "regain".to_owned()];
+        let check = any_pattern(check_starts_ends_with, &patterns);
+
+        assert!(check(LINE, &mut matches));
+        assert_eq!(matches, vec![0..4, 12..18, 19..23]);
+
+        matches.clear();
+        assert!(!check(b"run", &mut matches));
+        assert!(matches.is_empty());
+    }
 }
@@ -1,7 +1,11 @@
 mod base;
+#[cfg(test)]
+pub mod collect;
 mod ends_with;
 mod gen_search;
+mod line_regexp;
 mod max_count;
+mod regex;
 mod searcher;
 mod starts_ends_with;
 mod starts_with;
@@ -10,7 +14,9 @@ mod words;
 pub use base::Base;
 pub use ends_with::EndsWith;
 pub use gen_search::GenSearch;
+pub use line_regexp::LineRegexp;
 pub use max_count::MaxCount;
+pub use self::regex::Regex;
 pub use searcher::Searcher;
 pub use starts_ends_with::StartsEndsWith;
 pub use starts_with::StartsWith;
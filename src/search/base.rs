@@ -1,164 +1,36 @@
-use crate::results::{check_contains, CountResult, GenResult, SearchInnerResult, Upcast};
-use crate::search::Searcher;
-use bstr::{io::BufReadExt, ByteSlice};
-use std::io::BufRead;
-
-trait BaseSearch {
-    fn no_line_number(&mut self) -> GenResult;
-    fn no_line_number_caseless(&mut self) -> GenResult;
-    fn line_number(&mut self) -> GenResult;
-    fn line_number_caseless(&mut self) -> GenResult;
-    fn cnt(&mut self) -> GenResult;
-    fn cnt_caseless(&mut self) -> GenResult;
-}
+use crate::results::{any_literal, check_contains, invert_if};
+use crate::search::{GenSearch, Searcher};
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait Base {
-    fn get_matches(&mut self) -> GenResult;
-}
-
-// Closures try to borrow `self` as a whole so assign disjoint fields to
-// variables first
-impl<'a, R: BufRead> BaseSearch for Searcher<'a, R> {
-    fn cnt(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut cr = CountResult::default();
-
-        // TODO: The underlying buffer can be improved
-        reader.for_byte_line_with_terminator(|line| {
-            // TODO: Replace pattern by pattern.as_bytes() and corresponding
-            // function implementation
-            cr.check_and_add(pattern, line, check_contains);
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-
-    fn cnt_caseless(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut cr = CountResult::default();
-
-        // TODO: Redundant second pass over same line
-        reader.for_byte_line_with_terminator(|line| {
-            if line.is_ascii() {
-                cr.check_and_add(pattern, &line.to_ascii_lowercase(), check_contains);
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                cr.check_and_add(pattern, &buf, check_contains);
-            }
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-
-    fn no_line_number(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            sir.check_and_store_nln(pattern, line, check_contains);
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn no_line_number_caseless(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if line.is_ascii() {
-                sir.check_and_store_separate_nln(
-                    pattern,
-                    &line.to_ascii_lowercase(),
-                    line,
-                    check_contains,
-                );
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_nln(pattern, &buf, line, check_contains);
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn line_number(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            line_number += 1;
-            sir.check_and_store(pattern, line_number, line, check_contains);
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn line_number_caseless(&mut self) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            line_number += 1;
-            if line.is_ascii() {
-                sir.check_and_store_separate(
-                    pattern,
-                    line_number,
-                    &line.to_ascii_lowercase(),
-                    line,
-                    check_contains,
-                );
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate(pattern, line_number, &buf, line, check_contains);
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> Base for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
-        );
-
-        match (no_line_number, ignore_case, count) {
-            (true, true, false) => self.no_line_number_caseless(),
-            (true, false, false) => self.no_line_number(),
-            (false, true, false) => self.line_number_caseless(),
-            (false, false, false) => self.line_number(),
-            (_, true, true) => self.cnt_caseless(),
-            (_, false, true) => self.cnt(),
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let literals = matcher
+            .literals
+            .as_ref()
+            .expect("Should never panic: literals are built for this matcher type");
+        let check = invert_if(any_literal(check_contains, literals), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CountResult, Searcher};
-    use crate::matcher::MatcherBuilder;
-    use crate::results::{GenInnerResult, LineNumbers, SearchResult};
+    use super::Searcher;
+    use crate::matcher::{BinaryDetection, MatcherBuilder};
+    use crate::results::LineKind;
+    use crate::search::collect::Collected;
     use std::io::Cursor;
 
     const LINE: &str = "He started\nmade a run\n& stopped";
@@ -166,6 +38,10 @@ mod tests {
     const LINE_BIN2: &str = "He started\r\nmade a r\x00un\n& stopped";
     const LINE_BIN3: &str = "He started\r\nmade a r\x00un\r\n& stopped";
     const LINE_MAX_NON_ASCII: &str = "He started again\na\x00nd again\n& AΓain";
+    const LINE_CONTEXT: &str = "a\nb\nrun\nc\nd\ne\nrun\nf";
+    const LINE_CONTEXT2: &str = "run\na\nb\nc\nd\nrun";
+    const LINE_FOLD: &str = "ΣΊΣΥΦΟΣ Straße\nstrasse STRAẞE\nstrase";
+    const LINE_REPEAT: &str = "run run\nstop\nrun a run, rerun";
 
     #[test]
     fn find_no_match() {
@@ -175,7 +51,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(None)
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -185,9 +62,9 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let sr = SearchResult::default();
+        let sr = Collected::default();
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -198,7 +75,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -208,11 +86,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("made a run".into());
-        sr.line_numbers = LineNumbers::Some(vec![2]);
+        sr.line_numbers = vec![2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -223,7 +101,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -233,9 +112,9 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let sr = SearchResult::default();
+        let sr = Collected::default();
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -246,7 +125,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -256,11 +136,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("made a r\x00un".into());
-        sr.line_numbers = LineNumbers::Some(vec![2]);
+        sr.line_numbers = vec![2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -271,7 +151,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -281,11 +162,85 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("made a r\x00un".into());
-        sr.line_numbers = LineNumbers::Some(vec![2]);
+        sr.line_numbers = vec![2];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn search_binary_quit() {
+        let mut line = Cursor::new(LINE_BIN2.as_bytes());
+        let pattern = "made".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .binary(BinaryDetection::Quit)
+            .after_context(1)
+            .build(pattern)
+            .unwrap();
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let sr = Collected {
+            binary: true,
+            ..Default::default()
+        };
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn search_binary_skip() {
+        let mut line = Cursor::new(LINE_BIN2.as_bytes());
+        let pattern = "made".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .binary(BinaryDetection::Skip)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        assert_eq!(gir, Collected::default());
+    }
+
+    #[test]
+    fn cnt_binary_quit() {
+        let mut line = Cursor::new(LINE_BIN2.as_bytes());
+        let pattern = "e".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .binary(BinaryDetection::Quit)
+            .count(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 
     #[test]
@@ -297,7 +252,8 @@ mod tests {
             .ignore_case(true)
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -307,12 +263,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("He started again".into());
         sr.matches.push("a\x00nd again".into());
-        sr.line_numbers = LineNumbers::Some(vec![1, 2]);
+        sr.line_numbers = vec![1, 2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -324,7 +280,8 @@ mod tests {
             .ignore_case(true)
             .max_count(None)
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -334,10 +291,10 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("& AΓain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -345,7 +302,7 @@ mod tests {
         let mut line = Cursor::new(LINE_BIN3.as_bytes());
         let pattern = "t".to_owned();
 
-        let matcher = MatcherBuilder::new().count(true).build(pattern);
+        let matcher = MatcherBuilder::new().count(true).build(pattern).unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -355,8 +312,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 2 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 
     #[test]
@@ -367,7 +327,281 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .count(true)
             .ignore_case(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn line_number_invert() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "made".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .invert(true)
+            .no_line_number(false)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("He started".into());
+        sr.matches.push("& stopped".into());
+        sr.line_numbers = vec![1, 3];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn cnt_caseless_invert() {
+        let mut line = Cursor::new(LINE_MAX_NON_ASCII.as_bytes());
+        let pattern = "γ".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .ignore_case(true)
+            .invert(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn line_number_context() {
+        let mut line = Cursor::new(LINE_CONTEXT.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .before_context(2)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        for single_match in &["a", "b", "run", "c", "d", "e", "run", "f"] {
+            sr.matches.push((*single_match).into());
+        }
+        sr.line_numbers = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        sr.kinds = vec![
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Match,
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Match,
+            LineKind::Context,
+        ];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn huge_before_context() {
+        let mut line = Cursor::new(LINE_CONTEXT.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .before_context(usize::MAX)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        for single_match in &["a", "b", "run", "c", "d", "e", "run"] {
+            sr.matches.push((*single_match).into());
+        }
+        sr.line_numbers = vec![1, 2, 3, 4, 5, 6, 7];
+        sr.kinds = vec![
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Match,
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Match,
+        ];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn no_line_number_context() {
+        let mut line = Cursor::new(LINE_CONTEXT2.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .before_context(1)
+            .no_line_number(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        for single_match in &["run", "a", "d", "run"] {
+            sr.matches.push((*single_match).into());
+        }
+        sr.kinds = vec![
+            LineKind::Match,
+            LineKind::Context,
+            LineKind::Context,
+            LineKind::Match,
+        ];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn only_matching_spans() {
+        let mut line = Cursor::new(LINE_REPEAT.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .only_matching(true)
+            .no_line_number(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("run run".into());
+        sr.matches.push("run a run, rerun".into());
+        sr.spans = vec![vec![0..3, 4..7], vec![0..3, 6..9, 13..16]];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn cnt_count_matches() {
+        let pattern = "run".to_owned();
+        let mut builder = MatcherBuilder::new();
+        builder.count(true).count_matches(true);
+
+        for (max_count, invert, count) in [(None, false, 5), (Some(1), false, 2), (None, true, 1)] {
+            let matcher = builder
+                .invert(invert)
+                .max_count(max_count)
+                .build(pattern.clone())
+                .unwrap();
+
+            let searcher = Searcher {
+                reader: Cursor::new(LINE_REPEAT.as_bytes()),
+                matcher: &matcher,
+            };
+
+            let gen_result = searcher.search_matches();
+            let gir = gen_result.unwrap();
+
+            let cr = Collected {
+                count: Some(count),
+                ..Default::default()
+            };
+            assert_eq!(gir, cr);
+        }
+    }
+
+    #[test]
+    fn caseless_spans() {
+        let mut line = Cursor::new(LINE_FOLD.as_bytes());
+        let pattern = "STRASSE".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .ignore_case(true)
+            .only_matching(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("ΣΊΣΥΦΟΣ Straße".into());
+        sr.matches.push("strasse STRAẞE".into());
+        sr.line_numbers = vec![1, 2];
+        sr.spans = vec![vec![15..22], vec![0..7, 8..16]];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn byte_offset_context() {
+        let mut line = Cursor::new(LINE_CONTEXT.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .before_context(1)
+            .byte_offset(true)
+            .no_line_number(true)
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -377,7 +611,19 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 1 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let mut sr = Collected::default();
+        sr.matches.push("b".into());
+        sr.matches.push("run".into());
+        sr.matches.push("e".into());
+        sr.matches.push("run".into());
+        sr.kinds = vec![
+            LineKind::Context,
+            LineKind::Match,
+            LineKind::Context,
+            LineKind::Match,
+        ];
+        sr.byte_offsets = vec![2, 4, 12, 14];
+
+        assert_eq!(gir, sr);
     }
 }
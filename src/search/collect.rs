@@ -0,0 +1,100 @@
+//! Collects everything a `Searcher` reports in memory, so that tests can
+//! compare it at once.
+
+use crate::ext::ByteSliceExt;
+use crate::matcher::Config;
+use crate::results::LineKind;
+use crate::search::Searcher;
+use crate::sink::{CountKind, Sink, SinkLine};
+use bstr::BString;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+/// All results of a search. Apart from `matches`, the results of each line
+/// are only stored if the configuration asks for them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Collected {
+    /// Each line without its terminator
+    pub matches: Vec<BString>,
+    /// Unless line numbers are disabled
+    pub line_numbers: Vec<u64>,
+    /// If context was requested
+    pub kinds: Vec<LineKind>,
+    /// Byte spans of the matches in each line if only-matching was requested
+    pub spans: Vec<Vec<Range<usize>>>,
+    /// If byte offsets were requested
+    pub byte_offsets: Vec<u64>,
+    /// Whether a match was found in binary data
+    pub binary: bool,
+    /// Only set in count mode
+    pub count: Option<u64>,
+    /// Whether the input matches. Only set if files are listed
+    pub matched: bool,
+}
+
+#[derive(Debug)]
+struct CollectSink {
+    collected: Collected,
+    store_line_numbers: bool,
+    store_kinds: bool,
+    store_spans: bool,
+    store_byte_offsets: bool,
+}
+
+impl CollectSink {
+    fn new(config: &Config) -> Self {
+        Self {
+            collected: Collected::default(),
+            store_line_numbers: !config.no_line_number,
+            store_kinds: config.before_context > 0 || config.after_context > 0,
+            store_spans: config.only_matching,
+            store_byte_offsets: config.byte_offset,
+        }
+    }
+}
+
+impl Sink for CollectSink {
+    fn line(&mut self, line: &SinkLine) -> io::Result<bool> {
+        let collected = &mut self.collected;
+        collected
+            .matches
+            .push(line.line.without_terminator().into());
+        if self.store_line_numbers {
+            collected.line_numbers.push(line.line_number);
+        }
+        if self.store_kinds {
+            collected.kinds.push(line.kind);
+        }
+        if self.store_spans {
+            collected.spans.push(line.matches.to_vec());
+        }
+        if self.store_byte_offsets {
+            collected.byte_offsets.push(line.byte_offset);
+        }
+        Ok(true)
+    }
+
+    fn binary_match(&mut self) -> io::Result<()> {
+        self.collected.binary = true;
+        Ok(())
+    }
+
+    fn count(&mut self, count: u64, _: CountKind) -> io::Result<()> {
+        self.collected.count = Some(count);
+        Ok(())
+    }
+
+    fn matched(&mut self, matched: bool) -> io::Result<()> {
+        self.collected.matched = matched;
+        Ok(())
+    }
+}
+
+impl<'a, R: BufRead> Searcher<'a, R> {
+    /// Collects all results in memory
+    pub fn search_matches(self) -> io::Result<Collected> {
+        let mut sink = CollectSink::new(&self.matcher.config);
+        self.search_into(&mut sink)?;
+        Ok(sink.collected)
+    }
+}
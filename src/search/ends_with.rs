@@ -1,33 +1,26 @@
-use crate::results::{check_ends_with, GenResult};
+use crate::results::{any_literal, check_ends_with, invert_if};
 use crate::search::{GenSearch, Searcher};
-use std::io::BufRead;
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait EndsWith {
-    fn get_matches(&mut self) -> GenResult;
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> EndsWith for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, max_count, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.max_count,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
-        );
-
-        match (no_line_number, ignore_case, max_count, count) {
-            (true, true, Some(_), false) => self.no_line_number_caseless_max_count(check_ends_with),
-            (true, true, None, false) => self.no_line_number_caseless(check_ends_with),
-            (true, false, Some(_), false) => self.no_line_number_max_count(check_ends_with),
-            (true, false, None, false) => self.no_line_number(check_ends_with),
-            (false, true, Some(_), false) => self.line_number_caseless_max_count(check_ends_with),
-            (false, true, None, false) => self.line_number_caseless(check_ends_with),
-            (false, false, Some(_), false) => self.line_number_max_count(check_ends_with),
-            (false, false, None, false) => self.line_number(check_ends_with),
-            (_, true, Some(_), true) => self.cnt_caseless_max_count(check_ends_with),
-            (_, true, None, true) => self.cnt_caseless(check_ends_with),
-            (_, false, Some(_), true) => self.cnt_max_count(check_ends_with),
-            (_, false, None, true) => self.cnt(check_ends_with),
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let literals = matcher
+            .literals
+            .as_ref()
+            .expect("Should never panic: literals are built for this matcher type");
+        let check = invert_if(any_literal(check_ends_with, literals), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
@@ -36,7 +29,7 @@ impl<'a, R: BufRead> EndsWith for Searcher<'a, R> {
 mod tests {
     use super::Searcher;
     use crate::matcher::MatcherBuilder;
-    use crate::results::{CountResult, GenInnerResult, LineNumbers, SearchResult};
+    use crate::search::collect::Collected;
     use std::io::Cursor;
 
     const LINE: &str = "againn\ngain\na\x00nd, again\n& AΓain\nGain";
@@ -52,7 +45,8 @@ mod tests {
             .ignore_case(false)
             .max_count(Some(2))
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -62,12 +56,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("gain".into());
         sr.matches.push("a\x00nd, again".into());
-        sr.line_numbers = LineNumbers::Some(vec![2, 3]);
+        sr.line_numbers = vec![2, 3];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -80,7 +74,8 @@ mod tests {
             .ignore_case(true)
             .max_count(None)
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -90,11 +85,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("& AΓain".into());
-        sr.line_numbers = LineNumbers::Some(vec![4]);
+        sr.line_numbers = vec![4];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -106,7 +101,8 @@ mod tests {
             .ends_with(true)
             .ignore_case(true)
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -116,12 +112,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("Gain".into());
         sr.matches.push("a\x00nd, aGain".into());
         sr.matches.push("Gain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -133,7 +129,8 @@ mod tests {
             .ends_with(true)
             .max_count(Some(1))
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -143,10 +140,10 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("gain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -159,7 +156,8 @@ mod tests {
             .ignore_case(true)
             .max_count(Some(2))
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -169,11 +167,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("Gain".into());
         sr.matches.push("a\x00nd, aGain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -185,7 +183,8 @@ mod tests {
             .count(true)
             .ends_with(true)
             .max_count(Some(3))
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -195,7 +194,37 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 2 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn cnt_many_patterns_caseless() {
+        let mut line = Cursor::new(LINE2.as_bytes());
+        let patterns = vec!["NN".to_owned(), "gain".to_owned()];
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .ends_with(true)
+            .ignore_case(true)
+            .build_many(patterns)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 }
@@ -1,363 +1,326 @@
-use crate::results::{CountResult, GenResult, SearchInnerResult, Upcast};
+use crate::results::{CountResult, LineKind};
 use crate::search::Searcher;
-use bstr::{io::BufReadExt, ByteSlice};
-use std::io::BufRead;
+use crate::sink::{CountKind, Sink, SinkLine};
+use bstr::{io::BufReadExt, BString, ByteSlice};
+use caseless::Caseless;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::{iter, str};
 
 // Explicit lifetime annotation is required as it has to match the annotation
 // used when defining the check functions in results (which was in this
 // case omitted and hence inferred)
 pub trait GenSearch {
-    fn no_line_number<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn no_line_number_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn no_line_number_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn no_line_number_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn line_number<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(&mut self, check: F)
-        -> GenResult;
-    fn line_number_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn line_number_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn line_number_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn cnt_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn cnt_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn cnt_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult;
-    fn cnt<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(&mut self, check: F) -> GenResult;
+    fn search<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink;
+    fn cnt<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink;
+    fn first_match<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink;
 }
 
 impl<'a, R: BufRead> GenSearch for Searcher<'a, R> {
-    fn cnt<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(&mut self, check: F) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            cr.check_and_add(pattern, line, &check);
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-    fn cnt_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, matches_left) = (
+    fn cnt<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink,
+    {
+        let (reader, caseless, occurrences, max_count) = (
             &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
+            self.matcher.fold_lines(),
+            self.matcher.config.count_matches,
+            self.matcher.config.max_count.unwrap_or(u64::MAX),
         );
 
+        let mut folder = CaseFolder::default();
+        let mut matches = Vec::new();
         let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            cr.check_and_add(pattern, line, &check);
-            Ok(true)
-        })?;
-
-        cr.upcast()
+        let mut lines = 0;
+
+        if max_count > 0 {
+            reader.for_byte_line_with_terminator(|line| {
+                let line = folder.fold(line, caseless);
+                if cr.check_and_add(line, &mut matches, occurrences, &check) {
+                    lines += 1;
+                }
+                // Like grep, stop reading once --max-count lines have matched
+                Ok(lines < max_count)
+            })?;
+        }
+
+        // Inverted selections never contain matches, so their lines are
+        // counted instead
+        let kind = if occurrences && !self.matcher.config.invert {
+            CountKind::Matches
+        } else {
+            CountKind::Lines
+        };
+        sink.count(cr.count, kind)
     }
-    fn cnt_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                cr.check_and_add(pattern, &line_lower, &check);
-            } else {
-                // TODO: Better to reuse the buffer instead of creating a new
-                // one on every pass
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                cr.check_and_add(pattern, &buf, &check);
-            }
-            Ok(true)
-        })?;
 
-        cr.upcast()
-    }
-    fn cnt_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, matches_left) = (
+    fn first_match<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink,
+    {
+        let (reader, caseless, max_count) = (
             &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
+            self.matcher.fold_lines(),
+            self.matcher.config.max_count,
         );
 
-        let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                cr.check_and_add(pattern, &line_lower, &check);
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                cr.check_and_add(pattern, &buf, &check);
-            }
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-
-    fn no_line_number<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            sir.check_and_store_nln(pattern, line, &check);
-            Ok(true)
-        })?;
-
-        sir.upcast()
+        let mut folder = CaseFolder::default();
+        let mut matches = Vec::new();
+        let mut matched = false;
+
+        // Reading stops at the first match; neither line numbers nor context
+        // are tracked and no line is passed to the sink
+        if max_count != Some(0) {
+            reader.for_byte_line_with_terminator(|line| {
+                matches.clear();
+                matched = check(folder.fold(line, caseless), &mut matches);
+                Ok(!matched)
+            })?;
+        }
+
+        sink.matched(matched)
     }
 
-    fn no_line_number_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                sir.check_and_store_separate_nln(pattern, &line_lower, line, &check);
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_nln(pattern, &buf, line, &check);
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
+    fn search<F, S>(&mut self, check: F, sink: &mut S) -> io::Result<()>
+    where
+        F: for<'r, 'm> Fn(&'r [u8], &'m mut Vec<Range<usize>>) -> bool,
+        S: Sink,
+    {
+        if self.matcher.config.lists_files() {
+            return self.first_match(check, sink);
+        }
 
-    fn no_line_number_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
+        let (reader, caseless, config) = (
             &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
+            self.matcher.fold_lines(),
+            &self.matcher.config,
         );
 
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            sir.check_and_store_nln_max_count(pattern, line, &mut matches_left, &check);
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn no_line_number_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
+        let mut matches_left = config.max_count.unwrap_or(u64::MAX);
+        let mut line_number = 0;
+        let mut byte_offset = 0;
+        let mut folder = CaseFolder::default();
+        let mut matches = Vec::new();
+        let mut context = ContextBuffer::new(config.before_context, config.after_context);
 
-        let mut sir = SearchInnerResult::default();
+        if matches_left == 0 {
+            return Ok(());
+        }
 
+        // Reading stops as soon as --max-count lines and their trailing
+        // context have been passed to the sink
         reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
+            line_number += 1;
+            let line_offset = byte_offset;
+            byte_offset += line.len() as u64;
+            if matches_left == 0 {
+                let keep_going = context.non_match(sink, line_number, line_offset, line)?;
+                return Ok(keep_going && context.after_left > 0);
             }
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                sir.check_and_store_separate_nln_max_count(
-                    pattern,
-                    &line_lower,
+            matches.clear();
+            if check(folder.fold(line, caseless), &mut matches) {
+                matches_left -= 1;
+                if !context.matched(sink)? {
+                    return Ok(false);
+                }
+                folder.map_back(line.len(), &mut matches);
+                let line = SinkLine {
+                    line_number,
+                    byte_offset: line_offset,
                     line,
-                    &mut matches_left,
-                    &check,
-                );
+                    matches: &matches,
+                    kind: LineKind::Match,
+                };
+                let keep_going = sink.line(&line)?;
+                Ok(keep_going && (matches_left > 0 || context.after_left > 0))
             } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_nln_max_count(
-                    pattern,
-                    &buf,
-                    line,
-                    &mut matches_left,
-                    &check,
-                );
+                context.non_match(sink, line_number, line_offset, line)
             }
-            Ok(true)
-        })?;
-
-        sir.upcast()
+        })
     }
+}
 
-    fn line_number<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
+/// Case folds lines and maps offsets in the folded line back to the line it
+/// was folded from. Both buffers are reused for every line to avoid
+/// allocations per line
+#[derive(Debug, Default)]
+struct CaseFolder {
+    folded: Vec<u8>,
+    /// Byte range of the character in the original line each byte of
+    /// `folded` stems from. Empty if offsets do not need to be mapped
+    origins: Vec<(usize, usize)>,
+}
 
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
+impl CaseFolder {
+    /// Returns `line` as is or, if `caseless` is set, case folded. Full case
+    /// folding is used, so e.g. `ß` becomes `ss` and `ς` becomes `σ`. Invalid
+    /// UTF-8 is kept as is
+    fn fold<'b>(&'b mut self, line: &'b [u8], caseless: bool) -> &'b [u8] {
+        self.origins.clear();
+        if !caseless {
+            return line;
+        }
+        self.folded.clear();
+        if line.is_ascii() {
+            // Folding ASCII never changes offsets
+            self.folded.extend_from_slice(line);
+            self.folded.make_ascii_lowercase();
+            return &self.folded;
+        }
+        let mut utf8 = [0; 4];
+        for (start, end, c) in line.char_indices() {
+            let bytes = &line[start..end];
+            if str::from_utf8(bytes).is_err() {
+                self.folded.extend_from_slice(bytes);
+            } else {
+                for folded in iter::once(c).default_case_fold() {
+                    let folded = folded.encode_utf8(&mut utf8);
+                    self.folded.extend_from_slice(folded.as_bytes());
+                }
+            }
+            self.origins.resize(self.folded.len(), (start, end));
+        }
+        &self.folded
+    }
 
-        reader.for_byte_line_with_terminator(|line| {
-            line_number += 1;
+    /// Maps `matches` in the line last folded back to the original line of
+    /// `len` bytes. A match covering only part of a folded character (e.g.
+    /// one `s` of `ß`) covers the whole original character
+    fn map_back(&self, len: usize, matches: &mut [Range<usize>]) {
+        if self.origins.is_empty() {
+            return;
+        }
+        for m in matches {
+            let start = self.origins.get(m.start).map_or(len, |origin| origin.0);
+            let end = if m.end > m.start {
+                self.origins[m.end - 1].1
+            } else {
+                start
+            };
+            *m = start..end;
+        }
+    }
+}
 
-            sir.check_and_store(pattern, line_number, line, &check);
+/// Ring buffer of the lines preceding the next match together with the
+/// number of lines still to be shown after the previous match.
+#[derive(Debug)]
+struct ContextBuffer {
+    /// Line number, byte offset and contents of each line
+    before: VecDeque<(u64, u64, BString)>,
+    before_context: usize,
+    after_context: usize,
+    after_left: usize,
+}
 
-            Ok(true)
-        })?;
+impl ContextBuffer {
+    const fn new(before_context: usize, after_context: usize) -> Self {
+        Self {
+            // The buffer grows one line at a time, so a huge NUM costs nothing
+            // up front
+            before: VecDeque::new(),
+            before_context,
+            after_context,
+            after_left: 0,
+        }
+    }
 
-        sir.upcast()
+    /// Passes all buffered lines preceding a match to `sink`
+    fn matched<S: Sink>(&mut self, sink: &mut S) -> io::Result<bool> {
+        self.after_left = self.after_context;
+        for (line_number, byte_offset, line) in self.before.drain(..) {
+            let line = SinkLine {
+                line_number,
+                byte_offset,
+                line: &line,
+                matches: &[],
+                kind: LineKind::Context,
+            };
+            if !sink.line(&line)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
-    fn line_number_caseless<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
+    /// Passes `line` to `sink` if it follows a match closely enough and
+    /// otherwise keeps it around in case one of the next lines matches
+    fn non_match<S: Sink>(
         &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern) = (&mut self.reader, self.matcher.pattern.as_bytes());
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            line_number += 1;
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                sir.check_and_store_separate(pattern, line_number, &line_lower, line, &check);
+        sink: &mut S,
+        line_number: u64,
+        byte_offset: u64,
+        line: &[u8],
+    ) -> io::Result<bool> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            let line = SinkLine {
+                line_number,
+                byte_offset,
+                line,
+                matches: &[],
+                kind: LineKind::Context,
+            };
+            return sink.line(&line);
+        } else if self.before_context > 0 {
+            // Reuse the allocation of the oldest line once the buffer is full
+            let mut entry = if self.before.len() == self.before_context {
+                self.before.pop_front().unwrap_or_default()
             } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate(pattern, line_number, &buf, line, &check);
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
+                Default::default()
+            };
+            entry.0 = line_number;
+            entry.1 = byte_offset;
+            entry.2.clear();
+            entry.2.extend_from_slice(line);
+            self.before.push_back(entry);
+        }
+        Ok(true)
     }
+}
 
-    fn line_number_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
+#[cfg(test)]
+mod tests {
+    use super::CaseFolder;
+    use std::str;
 
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
+    #[test]
+    fn fold_and_map_back() {
+        let line = "Straße ΣΊΣΥΦΟΣ\n".as_bytes();
+        let mut folder = CaseFolder::default();
 
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            line_number += 1;
-            sir.check_and_store_max_count(pattern, line_number, line, &mut matches_left, &check);
-            Ok(true)
-        })?;
+        let folded = folder.fold(line, true);
+        assert_eq!(str::from_utf8(folded).unwrap(), "strasse σίσυφοσ\n");
 
-        sir.upcast()
+        // `sse` in `strasse` and `ίσ` in `σίσυφοσ`
+        let mut matches = vec![4..7, 10..14];
+        folder.map_back(line.len(), &mut matches);
+        assert_eq!(&line[matches[0].clone()], "ße".as_bytes());
+        assert_eq!(&line[matches[1].clone()], "ΊΣ".as_bytes());
     }
 
-    fn line_number_caseless_max_count<F: for<'r, 's> Fn(&'r [u8], &'s [u8]) -> bool>(
-        &mut self,
-        check: F,
-    ) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
+    #[test]
+    fn fold_invalid_utf8() {
+        let line = b"\xffAB\xe2\x98 \xc3\x9f";
+        let mut folder = CaseFolder::default();
 
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            line_number += 1;
-            if line.is_ascii() {
-                let line_lower = line.to_ascii_lowercase();
-                sir.check_and_store_separate_max_count(
-                    pattern,
-                    line_number,
-                    &line_lower,
-                    line,
-                    &mut matches_left,
-                    &check,
-                );
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_max_count(
-                    pattern,
-                    line_number,
-                    &buf,
-                    line,
-                    &mut matches_left,
-                    &check,
-                );
-            }
-            Ok(true)
-        })?;
+        assert_eq!(folder.fold(line, true), b"\xffab\xe2\x98 ss");
+        let mut matches = vec![0..3, 7..8, 8..8];
+        folder.map_back(line.len(), &mut matches);
+        assert_eq!(matches, vec![0..3, 6..8, 8..8]);
 
-        sir.upcast()
+        assert_eq!(folder.fold(line, false), line);
     }
 }
@@ -0,0 +1,121 @@
+use crate::results::{any_literal, check_line, invert_if};
+use crate::search::{GenSearch, Searcher};
+use crate::sink::Sink;
+use std::io::{self, BufRead};
+
+pub trait LineRegexp {
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
+}
+
+impl<'a, R: BufRead> LineRegexp for Searcher<'a, R> {
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let lines = matcher
+            .lines
+            .as_ref()
+            .expect("Should never panic: lines are built for this matcher type");
+        let check = invert_if(any_literal(check_line, lines), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Searcher;
+    use crate::matcher::MatcherBuilder;
+    use crate::search::collect::Collected;
+    use std::io::Cursor;
+
+    const LINE: &str = "gain\r\nregain\nGain\ngain again\nAΓain\ngain";
+
+    #[test]
+    fn whole_lines() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .line_regexp(true)
+            .only_matching(true)
+            .words(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("gain".into());
+        sr.matches.push("gain".into());
+        sr.line_numbers = vec![1, 6];
+        sr.spans = vec![vec![0..4], vec![0..4]];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn many_patterns_caseless() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let patterns = vec!["GAIN".to_owned(), "aγain".to_owned()];
+
+        let matcher = MatcherBuilder::new()
+            .ignore_case(true)
+            .line_regexp(true)
+            .build_many(patterns)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("gain".into());
+        sr.matches.push("Gain".into());
+        sr.matches.push("AΓain".into());
+        sr.matches.push("gain".into());
+        sr.line_numbers = vec![1, 3, 5, 6];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn cnt_invert() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .invert(true)
+            .line_regexp(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+}
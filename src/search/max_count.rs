@@ -1,224 +1,50 @@
-use crate::results::{check_contains, CountResult, GenResult, SearchInnerResult, Upcast};
-use crate::search::Searcher;
-use bstr::{io::BufReadExt, ByteSlice};
-use std::io::BufRead;
-
-trait MaxCountSearch {
-    fn no_line_number(&mut self) -> GenResult;
-    fn no_line_number_caseless(&mut self) -> GenResult;
-    fn line_number(&mut self) -> GenResult;
-    fn line_number_caseless(&mut self) -> GenResult;
-    fn cnt(&mut self) -> GenResult;
-    fn cnt_caseless(&mut self) -> GenResult;
-}
+use crate::results::{any_literal, check_contains, invert_if};
+use crate::search::{GenSearch, Searcher};
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait MaxCount {
-    fn get_matches(&mut self) -> GenResult;
-}
-
-impl<'a, R: BufRead> MaxCountSearch for Searcher<'a, R> {
-    fn cnt(&mut self) -> GenResult {
-        let (reader, pattern, matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            cr.check_and_add(pattern, line, check_contains);
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-
-    fn cnt_caseless(&mut self) -> GenResult {
-        let (reader, pattern, matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut cr = CountResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            if line.is_ascii() {
-                cr.check_and_add(pattern, &line.to_ascii_lowercase(), check_contains);
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                cr.check_and_add(pattern, &buf, check_contains);
-            }
-            Ok(true)
-        })?;
-
-        cr.upcast()
-    }
-    fn no_line_number(&mut self) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            sir.check_and_store_nln_max_count(pattern, line, &mut matches_left, check_contains);
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn no_line_number_caseless(&mut self) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            if line.is_ascii() {
-                sir.check_and_store_separate_nln_max_count(
-                    pattern,
-                    &line.to_ascii_lowercase(),
-                    line,
-                    &mut matches_left,
-                    check_contains,
-                );
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_nln_max_count(
-                    pattern,
-                    &buf,
-                    line,
-                    &mut matches_left,
-                    check_contains,
-                );
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn line_number(&mut self) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            line_number += 1;
-            sir.check_and_store_max_count(
-                pattern,
-                line_number,
-                line,
-                &mut matches_left,
-                check_contains,
-            );
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
-
-    fn line_number_caseless(&mut self) -> GenResult {
-        let (reader, pattern, mut matches_left) = (
-            &mut self.reader,
-            self.matcher.pattern.as_bytes(),
-            self.matcher.config.max_count.unwrap(),
-        );
-
-        let mut line_number = 0;
-        let mut sir = SearchInnerResult::default();
-
-        reader.for_byte_line_with_terminator(|line| {
-            if matches_left == 0 as u64 {
-                return Ok(true);
-            }
-            line_number += 1;
-            if line.is_ascii() {
-                sir.check_and_store_separate_max_count(
-                    pattern,
-                    line_number,
-                    &line.to_ascii_lowercase(),
-                    line,
-                    &mut matches_left,
-                    check_contains,
-                );
-            } else {
-                let mut buf = Default::default();
-                line.to_lowercase_into(&mut buf);
-                sir.check_and_store_separate_max_count(
-                    pattern,
-                    line_number,
-                    &buf,
-                    line,
-                    &mut matches_left,
-                    check_contains,
-                );
-            }
-            Ok(true)
-        })?;
-
-        sir.upcast()
-    }
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> MaxCount for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
-        );
-
-        match (no_line_number, ignore_case, count) {
-            (true, true, false) => self.no_line_number_caseless(),
-            (true, false, false) => self.no_line_number(),
-            (false, true, false) => self.line_number_caseless(),
-            (false, false, false) => self.line_number(),
-            (_, true, true) => self.cnt_caseless(),
-            (_, false, true) => self.cnt(),
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let literals = matcher
+            .literals
+            .as_ref()
+            .expect("Should never panic: literals are built for this matcher type");
+        let check = invert_if(any_literal(check_contains, literals), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CountResult, Searcher};
+    use super::Searcher;
     use crate::matcher::MatcherBuilder;
-    use crate::results::{GenInnerResult, LineNumbers, SearchResult};
-    use std::io::Cursor;
+    use crate::results::LineKind;
+    use crate::search::collect::Collected;
+    use std::io::{self, BufReader, Cursor, Read};
+
+    /// Fails on every read, so searches which read too far return an error
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past --max-count"))
+        }
+    }
 
     const LINE: &str = "He started\nmade a run\n& stopped";
     const LINE_MAX_NON_ASCII: &str = "He started again\na\x00nd again\n& AΓain";
+    const LINE_CONTEXT: &str = "run\nrun\na\nrun";
 
     #[test]
     fn max_count_empty() {
@@ -228,7 +54,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(Some(0))
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -238,8 +65,8 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let sr = SearchResult::default();
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        let sr = Collected::default();
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -250,7 +77,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(Some(1))
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -260,11 +88,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("He started again".into());
-        sr.line_numbers = LineNumbers::Some(vec![1]);
+        sr.line_numbers = vec![1];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -275,7 +103,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .max_count(Some(1000))
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -285,11 +114,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("made a run".into());
-        sr.line_numbers = LineNumbers::Some(vec![2]);
+        sr.line_numbers = vec![2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -301,7 +130,8 @@ mod tests {
             .ignore_case(true)
             .max_count(Some(2))
             .no_line_number(false)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -311,12 +141,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("He started again".into());
         sr.matches.push("a\x00nd again".into());
-        sr.line_numbers = LineNumbers::Some(vec![1, 2]);
+        sr.line_numbers = vec![1, 2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -328,7 +158,8 @@ mod tests {
             .ignore_case(true)
             .max_count(Some(2))
             .no_line_number(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -338,11 +169,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("He started again".into());
         sr.matches.push("a\x00nd again".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -353,7 +184,8 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .count(true)
             .max_count(Some(0))
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -363,8 +195,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 0 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 
     #[test]
@@ -375,7 +210,35 @@ mod tests {
         let matcher = MatcherBuilder::new()
             .count(true)
             .max_count(Some(2))
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn no_line_number_invert() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "made".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .invert(true)
+            .max_count(Some(1))
+            .no_line_number(true)
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -385,7 +248,122 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 3 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let mut sr = Collected::default();
+        sr.matches.push("He started".into());
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn line_number_trailing_context() {
+        let mut line = Cursor::new(LINE_CONTEXT.as_bytes());
+        let pattern = "run".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .after_context(2)
+            .max_count(Some(1))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        // Like grep, lines after the last match are shown as context even if
+        // they match themselves
+        let mut sr = Collected::default();
+        for single_match in &["run", "run", "a"] {
+            sr.matches.push((*single_match).into());
+        }
+        sr.line_numbers = vec![1, 2, 3];
+        sr.kinds = vec![LineKind::Match, LineKind::Context, LineKind::Context];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn max_count_stops_reading() {
+        let reader = Cursor::new(LINE_MAX_NON_ASCII.as_bytes()).chain(FailingReader);
+        let pattern = "again".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .max_count(Some(1))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: BufReader::with_capacity(4, reader),
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("He started again".into());
+        sr.matches.push("a\x00nd again".into());
+        sr.line_numbers = vec![1, 2];
+        sr.kinds = vec![LineKind::Match, LineKind::Context];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn first_match_stops_reading() {
+        let reader = Cursor::new(LINE_MAX_NON_ASCII.as_bytes()).chain(FailingReader);
+        let pattern = "again".to_owned();
+
+        // Listing files overrides counting
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .files_with_matches(true)
+            .max_count(Some(2))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: BufReader::with_capacity(4, reader),
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let sr = Collected {
+            matched: true,
+            ..Default::default()
+        };
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn cnt_max_count_stops_reading() {
+        let reader = Cursor::new(LINE_MAX_NON_ASCII.as_bytes()).chain(FailingReader);
+        let pattern = "again".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .max_count(Some(2))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: BufReader::with_capacity(4, reader),
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 }
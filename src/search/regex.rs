@@ -0,0 +1,207 @@
+use crate::ext::ByteSliceExt;
+use crate::results::invert_if;
+use crate::search::{GenSearch, Searcher};
+use crate::sink::Sink;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+pub trait Regex {
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
+}
+
+impl<'a, R: BufRead> Regex for Searcher<'a, R> {
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        // Without any patterns (e.g. from an empty -f FILE) no regex is
+        // compiled and no line matches
+        let regex = matcher.regex.as_ref();
+
+        // The regex is compiled with the requested case sensitivity, so lines
+        // are never lowercased. Terminators are stripped to make `$` match at
+        // the end of each line
+        let check = invert_if(
+            |line: &[u8], matches: &mut Vec<Range<usize>>| {
+                if let Some(regex) = regex {
+                    let found = regex.find_iter(line.without_terminator());
+                    matches.extend(found.map(|m| m.range()));
+                }
+                !matches.is_empty()
+            },
+            invert,
+        );
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Searcher;
+    use crate::matcher::MatcherBuilder;
+    use crate::search::collect::Collected;
+    use std::io::Cursor;
+
+    const LINE: &str = "He started\nmade a run\r\n& stopped\nmad\x00e a RUN";
+
+    #[test]
+    fn line_number() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = r"ma\w+ a r".to_owned();
+
+        let matcher = MatcherBuilder::new().regex(true).build(pattern).unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("made a run".into());
+        sr.line_numbers = vec![2];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn no_line_number_caseless_max_count() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = r"\Wrun$".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .ignore_case(true)
+            .max_count(Some(2))
+            .no_line_number(true)
+            .regex(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("made a run".into());
+        sr.matches.push("mad\x00e a RUN".into());
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn cnt() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "^(He|&) ".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .regex(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let pattern = "(unclosed".to_owned();
+
+        let matcher = MatcherBuilder::new().regex(true).build(pattern);
+
+        assert!(matcher.is_err());
+    }
+
+    #[test]
+    fn many_patterns() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let patterns = vec!["^He".to_owned(), "stopped$".to_owned()];
+
+        let matcher = MatcherBuilder::new()
+            .regex(true)
+            .build_many(patterns)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("He started".into());
+        sr.matches.push("& stopped".into());
+        sr.line_numbers = vec![1, 3];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn line_regexp() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let patterns = vec!["made a \\w+".to_owned(), "stop".to_owned()];
+
+        let matcher = MatcherBuilder::new()
+            .line_regexp(true)
+            .regex(true)
+            .build_many(patterns)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("made a run".into());
+        sr.line_numbers = vec![2];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn no_patterns() {
+        let mut line = Cursor::new(LINE.as_bytes());
+
+        let matcher = MatcherBuilder::new()
+            .regex(true)
+            .build_many(Vec::new())
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        assert_eq!(gir, Collected::default());
+    }
+}
@@ -1,7 +1,15 @@
-use crate::matcher::{Matcher, MatcherType};
-use crate::results::GenResult;
-use crate::search::{Base, EndsWith, MaxCount, StartsEndsWith, StartsWith, Words};
-use std::io::BufRead;
+use crate::matcher::{BinaryDetection, Matcher, MatcherType};
+use crate::results::LineKind;
+use crate::search::{
+    Base, EndsWith, LineRegexp, MaxCount, Regex, StartsEndsWith, StartsWith, Words,
+};
+use crate::sink::{CountKind, Sink, SinkLine};
+use std::io::{self, BufRead};
+
+/// Number of bytes at the start of an input which are checked for a NUL byte
+/// to detect binary data. This is the default capacity of a `BufReader`, so
+/// that memory mapped files are treated like read ones
+const BINARY_DETECTION_LEN: usize = 8 * 1024;
 
 #[derive(Debug)]
 pub struct Searcher<'a, R> {
@@ -10,15 +18,68 @@ pub struct Searcher<'a, R> {
 }
 
 impl<'a, R: BufRead> Searcher<'a, R> {
-    pub fn search_matches(mut self) -> GenResult {
+    /// Searches all lines and passes them to `sink` as soon as they are found.
+    /// Inputs with a NUL byte in their first block are handled according to
+    /// the configured `BinaryDetection`
+    pub fn search_into<S: Sink>(mut self, sink: &mut S) -> io::Result<()> {
+        let config = &self.matcher.config;
+        let binary = config.binary != BinaryDetection::Text && {
+            let block = self.reader.fill_buf()?;
+            block[..block.len().min(BINARY_DETECTION_LEN)].contains(&b'\0')
+        };
+
+        match (binary, config.binary) {
+            (true, BinaryDetection::Skip) => {}
+            // Counts and listed files are printed as usual, only lines are
+            // withheld
+            (true, BinaryDetection::Quit) if !config.count && !config.lists_files() => {
+                self.dispatch(&mut BinarySink { sink })?
+            }
+            _ => self.dispatch(sink)?,
+        }
+        sink.finish()
+    }
+
+    fn dispatch<S: Sink>(mut self, sink: &mut S) -> io::Result<()> {
         let matcher_type = &self.matcher.matcher_type;
         match matcher_type {
-            MatcherType::Base => Base::get_matches(&mut self),
-            MatcherType::EndsWith => EndsWith::get_matches(&mut self),
-            MatcherType::MaxCount => MaxCount::get_matches(&mut self),
-            MatcherType::StartsWith => StartsWith::get_matches(&mut self),
-            MatcherType::StartsEndsWith => StartsEndsWith::get_matches(&mut self),
-            MatcherType::Words => Words::get_matches(&mut self),
+            MatcherType::Base => Base::get_matches(&mut self, sink),
+            MatcherType::EndsWith => EndsWith::get_matches(&mut self, sink),
+            MatcherType::LineRegexp => LineRegexp::get_matches(&mut self, sink),
+            MatcherType::MaxCount => MaxCount::get_matches(&mut self, sink),
+            MatcherType::Regex => Regex::get_matches(&mut self, sink),
+            MatcherType::StartsWith => StartsWith::get_matches(&mut self, sink),
+            MatcherType::StartsEndsWith => StartsEndsWith::get_matches(&mut self, sink),
+            MatcherType::Words => Words::get_matches(&mut self, sink),
         }
     }
 }
+
+/// Reports the first match of a binary input instead of passing on any lines
+struct BinarySink<'s, S> {
+    sink: &'s mut S,
+}
+
+impl<'s, S: Sink> Sink for BinarySink<'s, S> {
+    fn line(&mut self, line: &SinkLine) -> io::Result<bool> {
+        match line.kind {
+            LineKind::Match => {
+                self.sink.binary_match()?;
+                Ok(false)
+            }
+            LineKind::Context => Ok(true),
+        }
+    }
+
+    fn binary_match(&mut self) -> io::Result<()> {
+        self.sink.binary_match()
+    }
+
+    fn count(&mut self, count: u64, kind: CountKind) -> io::Result<()> {
+        self.sink.count(count, kind)
+    }
+
+    fn matched(&mut self, matched: bool) -> io::Result<()> {
+        self.sink.matched(matched)
+    }
+}
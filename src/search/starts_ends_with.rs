@@ -1,37 +1,25 @@
-use crate::results::{check_starts_ends_with, GenResult};
+use crate::results::{any_pattern, check_starts_ends_with, invert_if};
 use crate::search::{GenSearch, Searcher};
-use std::io::BufRead;
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait StartsEndsWith {
-    fn get_matches(&mut self) -> GenResult;
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> StartsEndsWith for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, max_count, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.max_count,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let check = invert_if(
+            any_pattern(check_starts_ends_with, &matcher.patterns),
+            invert,
         );
 
-        match (no_line_number, ignore_case, max_count, count) {
-            (true, true, Some(_), false) => {
-                self.no_line_number_caseless_max_count(check_starts_ends_with)
-            }
-            (true, true, None, false) => self.no_line_number_caseless(check_starts_ends_with),
-            (true, false, Some(_), false) => self.no_line_number_max_count(check_starts_ends_with),
-            (true, false, None, false) => self.no_line_number(check_starts_ends_with),
-            (false, true, Some(_), false) => {
-                self.line_number_caseless_max_count(check_starts_ends_with)
-            }
-            (false, true, None, false) => self.line_number_caseless(check_starts_ends_with),
-            (false, false, Some(_), false) => self.line_number_max_count(check_starts_ends_with),
-            (false, false, None, false) => self.line_number(check_starts_ends_with),
-            (_, true, Some(_), true) => self.cnt_caseless_max_count(check_starts_ends_with),
-            (_, true, None, true) => self.cnt_caseless(check_starts_ends_with),
-            (_, false, Some(_), true) => self.cnt_max_count(check_starts_ends_with),
-            (_, false, None, true) => self.cnt(check_starts_ends_with),
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
@@ -40,7 +28,7 @@ impl<'a, R: BufRead> StartsEndsWith for Searcher<'a, R> {
 mod tests {
     use super::Searcher;
     use crate::matcher::MatcherBuilder;
-    use crate::results::{CountResult, GenInnerResult, LineNumbers, SearchResult};
+    use crate::search::collect::Collected;
     use std::io::Cursor;
 
     const LINE: &str = "gain\ngain,\na\x00nd, Gain,\n& AΓain\ngain,";
@@ -56,7 +44,8 @@ mod tests {
             .max_count(Some(1))
             .no_line_number(false)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -66,11 +55,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("gain,".into());
-        sr.line_numbers = LineNumbers::Some(vec![2]);
+        sr.line_numbers = vec![2];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -84,7 +73,8 @@ mod tests {
             .ignore_case(true)
             .max_count(Some(1))
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -94,7 +84,10 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 3 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
     }
 }
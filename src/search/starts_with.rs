@@ -1,35 +1,26 @@
-use crate::results::{check_starts_with, GenResult};
+use crate::results::{any_literal, check_starts_with, invert_if};
 use crate::search::{GenSearch, Searcher};
-use std::io::BufRead;
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait StartsWith {
-    fn get_matches(&mut self) -> GenResult;
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> StartsWith for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, max_count, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.max_count,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
-        );
-
-        match (no_line_number, ignore_case, max_count, count) {
-            (true, true, Some(_), false) => {
-                self.no_line_number_caseless_max_count(check_starts_with)
-            }
-            (true, true, None, false) => self.no_line_number_caseless(check_starts_with),
-            (true, false, Some(_), false) => self.no_line_number_max_count(check_starts_with),
-            (true, false, None, false) => self.no_line_number(check_starts_with),
-            (false, true, Some(_), false) => self.line_number_caseless_max_count(check_starts_with),
-            (false, true, None, false) => self.line_number_caseless(check_starts_with),
-            (false, false, Some(_), false) => self.line_number_max_count(check_starts_with),
-            (false, false, None, false) => self.line_number(check_starts_with),
-            (_, true, Some(_), true) => self.cnt_caseless_max_count(check_starts_with),
-            (_, true, None, true) => self.cnt_caseless(check_starts_with),
-            (_, false, Some(_), true) => self.cnt_max_count(check_starts_with),
-            (_, false, None, true) => self.cnt(check_starts_with),
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let literals = matcher
+            .literals
+            .as_ref()
+            .expect("Should never panic: literals are built for this matcher type");
+        let check = invert_if(any_literal(check_starts_with, literals), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
@@ -38,11 +29,12 @@ impl<'a, R: BufRead> StartsWith for Searcher<'a, R> {
 mod tests {
     use super::Searcher;
     use crate::matcher::MatcherBuilder;
-    use crate::results::{CountResult, GenInnerResult, LineNumbers, SearchResult};
+    use crate::search::collect::Collected;
     use std::io::Cursor;
 
     const LINE: &str = "again\na\tgain\na\x00nd, gain\n&\u{2003}AΓain\nGain";
     const LINE2: &str = "again\nGain\na\x00nd, gain\n& AΓain\nGain";
+    const LINE_REPEAT: &str = "gain again gained\nno";
 
     #[test]
     fn line_number() {
@@ -54,7 +46,8 @@ mod tests {
             .max_count(Some(2))
             .no_line_number(false)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -64,12 +57,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("a\tgain".into());
         sr.matches.push("a\x00nd, gain".into());
-        sr.line_numbers = LineNumbers::Some(vec![2, 3]);
+        sr.line_numbers = vec![2, 3];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -82,7 +75,8 @@ mod tests {
             .max_count(None)
             .no_line_number(false)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -92,11 +86,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("&\u{2003}AΓain".into());
-        sr.line_numbers = LineNumbers::Some(vec![4]);
+        sr.line_numbers = vec![4];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -108,7 +102,8 @@ mod tests {
             .ignore_case(true)
             .no_line_number(true)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -118,12 +113,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("a\tgain".into());
         sr.matches.push("a\x00nd, gain".into());
         sr.matches.push("Gain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -135,7 +130,8 @@ mod tests {
             .max_count(Some(2))
             .no_line_number(true)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -145,11 +141,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("a\tgain".into());
         sr.matches.push("a\x00nd, gain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -162,7 +158,8 @@ mod tests {
             .max_count(Some(2))
             .no_line_number(true)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -172,11 +169,11 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("Gain".into());
         sr.matches.push("a\x00nd, gain".into());
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -188,7 +185,8 @@ mod tests {
             .count(true)
             .ignore_case(true)
             .starts_with(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -198,7 +196,64 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 3 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn cnt_count_matches() {
+        let mut line = Cursor::new(LINE_REPEAT.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .count(true)
+            .count_matches(true)
+            .starts_with(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let cr = Collected {
+            count: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn only_matching_spans() {
+        let mut line = Cursor::new(LINE_REPEAT.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .only_matching(true)
+            .starts_with(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("gain again gained".into());
+        sr.line_numbers = vec![1];
+        sr.spans = vec![vec![0..4, 11..15]];
+
+        assert_eq!(gir, sr);
     }
 }
@@ -1,33 +1,22 @@
-use crate::results::{check_words, GenResult};
+use crate::results::{any_pattern, check_words, invert_if};
 use crate::search::{GenSearch, Searcher};
-use std::io::BufRead;
+use crate::sink::Sink;
+use std::io::{self, BufRead};
 
 pub trait Words {
-    fn get_matches(&mut self) -> GenResult;
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()>;
 }
 
 impl<'a, R: BufRead> Words for Searcher<'a, R> {
-    fn get_matches(&mut self) -> GenResult {
-        let (ignore_case, max_count, no_line_number, count) = (
-            self.matcher.config.ignore_case,
-            self.matcher.config.max_count,
-            self.matcher.config.no_line_number,
-            self.matcher.config.count,
-        );
-
-        match (no_line_number, ignore_case, max_count, count) {
-            (true, true, Some(_), false) => self.no_line_number_caseless_max_count(check_words),
-            (true, true, None, false) => self.no_line_number_caseless(check_words),
-            (true, false, Some(_), false) => self.no_line_number_max_count(check_words),
-            (true, false, None, false) => self.no_line_number(check_words),
-            (false, true, Some(_), false) => self.line_number_caseless_max_count(check_words),
-            (false, true, None, false) => self.line_number_caseless(check_words),
-            (false, false, Some(_), false) => self.line_number_max_count(check_words),
-            (false, false, None, false) => self.line_number(check_words),
-            (_, true, Some(_), true) => self.cnt_caseless_max_count(check_words),
-            (_, true, None, true) => self.cnt_caseless(check_words),
-            (_, false, Some(_), true) => self.cnt_max_count(check_words),
-            (_, false, None, true) => self.cnt(check_words),
+    fn get_matches<S: Sink>(&mut self, sink: &mut S) -> io::Result<()> {
+        let matcher = self.matcher;
+        let (count, invert) = (matcher.config.count, matcher.config.invert);
+        let check = invert_if(any_pattern(check_words, &matcher.patterns), invert);
+
+        if count {
+            self.cnt(check, sink)
+        } else {
+            self.search(check, sink)
         }
     }
 }
@@ -36,7 +25,7 @@ impl<'a, R: BufRead> Words for Searcher<'a, R> {
 mod tests {
     use super::Searcher;
     use crate::matcher::MatcherBuilder;
-    use crate::results::{CountResult, GenInnerResult, LineNumbers, SearchResult};
+    use crate::search::collect::Collected;
     use std::io::Cursor;
 
     const LINE: &str = "Gain's\n?gain,\na\x00nd, Gain,\n& AΓain\ngain,";
@@ -52,7 +41,8 @@ mod tests {
             .no_line_number(false)
             .starts_with(true)
             .words(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -62,12 +52,12 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let mut sr = SearchResult::default();
+        let mut sr = Collected::default();
         sr.matches.push("?gain,".into());
         sr.matches.push("a\x00nd, Gain,".into());
-        sr.line_numbers = LineNumbers::Some(vec![2, 3]);
+        sr.line_numbers = vec![2, 3];
 
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -80,7 +70,8 @@ mod tests {
             .max_count(Some(2))
             .no_line_number(true)
             .words(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -90,8 +81,8 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let sr = SearchResult::default();
-        assert_eq!(gir, GenInnerResult::Search(sr));
+        let sr = Collected::default();
+        assert_eq!(gir, sr);
     }
 
     #[test]
@@ -105,7 +96,8 @@ mod tests {
             .no_line_number(false)
             .starts_with(true)
             .words(true)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut line,
@@ -115,7 +107,95 @@ mod tests {
         let gen_result = searcher.search_matches();
         let gir = gen_result.unwrap();
 
-        let cr = CountResult { count: 3 };
-        assert_eq!(gir, GenInnerResult::Count(cr));
+        let cr = Collected {
+            count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(gir, cr);
+    }
+
+    #[test]
+    fn line_number_invert() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .invert(true)
+            .words(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("Gain's".into());
+        sr.matches.push("a\x00nd, Gain,".into());
+        sr.matches.push("& AΓain".into());
+        sr.line_numbers = vec![1, 3, 4];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn only_matching_spans() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let pattern = "gain".to_owned();
+
+        let matcher = MatcherBuilder::new()
+            .ignore_case(true)
+            .only_matching(true)
+            .words(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("?gain,".into());
+        sr.matches.push("a\x00nd, Gain,".into());
+        sr.matches.push("gain,".into());
+        sr.line_numbers = vec![2, 3, 5];
+        sr.spans = vec![vec![1..5], vec![6..10], vec![0..4]];
+
+        assert_eq!(gir, sr);
+    }
+
+    #[test]
+    fn many_patterns() {
+        let mut line = Cursor::new(LINE.as_bytes());
+        let patterns = vec!["gain".to_owned(), "AΓain".to_owned()];
+
+        let matcher = MatcherBuilder::new()
+            .words(true)
+            .build_many(patterns)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut line,
+            matcher: &matcher,
+        };
+
+        let gen_result = searcher.search_matches();
+        let gir = gen_result.unwrap();
+
+        let mut sr = Collected::default();
+        sr.matches.push("?gain,".into());
+        sr.matches.push("& AΓain".into());
+        sr.matches.push("gain,".into());
+        sr.line_numbers = vec![2, 4, 5];
+
+        assert_eq!(gir, sr);
     }
 }
@@ -0,0 +1,50 @@
+//! Receives lines from a `Searcher` as soon as they are found, so that they
+//! never have to be collected in memory.
+
+use crate::results::LineKind;
+use std::io;
+use std::ops::Range;
+
+/// A single line reported by a `Searcher`
+#[derive(Debug)]
+pub struct SinkLine<'b> {
+    pub line_number: u64,
+    /// Offset of the first byte of `line` in the input
+    pub byte_offset: u64,
+    /// The line including its terminator
+    pub line: &'b [u8],
+    /// Byte ranges of all matches in `line`. Empty for context lines
+    pub matches: &'b [Range<usize>],
+    pub kind: LineKind,
+}
+
+/// What a count passed to `Sink::count` counts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountKind {
+    /// Selected lines
+    Lines,
+    /// Every match in the selected lines
+    Matches,
+}
+
+pub trait Sink {
+    /// Called for every matching or context line in the order in which they
+    /// appear in the input. Returning `Ok(false)` stops the search.
+    fn line(&mut self, line: &SinkLine) -> io::Result<bool>;
+
+    /// Called instead of `line` for the first match in an input which was
+    /// detected as binary, after which the search stops
+    fn binary_match(&mut self) -> io::Result<()>;
+
+    /// Called once the whole input has been searched in count mode
+    fn count(&mut self, count: u64, kind: CountKind) -> io::Result<()>;
+
+    /// Called instead of `line` once it is known whether the input matches at
+    /// all, when only that is of interest (i.e. when listing files)
+    fn matched(&mut self, matched: bool) -> io::Result<()>;
+
+    /// Called once the search of an input is done, even if it was skipped
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
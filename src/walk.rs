@@ -0,0 +1,254 @@
+//! Collects the files to search when PATH points to a directory.
+
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore files specific to `sp`, which follow the `.gitignore`
+/// format and take precedence over `.gitignore` and `.ignore`
+const IGNORE_FILENAME: &str = ".spignore";
+
+/// Controls which files below a directory are searched
+#[derive(Clone, Debug, Default)]
+pub struct WalkConfig {
+    /// Search hidden files and directories
+    pub hidden: bool,
+    /// Do not respect `.gitignore`, `.ignore` and `.spignore` files
+    pub no_ignore: bool,
+    /// Follow symbolic links
+    pub follow: bool,
+    /// Globs which paths have to match, or must not match if prefixed with
+    /// `!`. They take precedence over ignore files and hidden files
+    pub globs: Vec<String>,
+    /// Like `globs`, but matched case insensitively
+    pub iglobs: Vec<String>,
+    /// Additional file types, each defined as `name:glob`
+    pub type_add: Vec<String>,
+    /// Names of the file types to search
+    pub types: Vec<String>,
+    /// Names of the file types not to search
+    pub types_not: Vec<String>,
+}
+
+/// Recursively visits `root` and yields every regular file below it which is
+/// not excluded by `config`. Entries are sorted by file name so that output
+/// is stable across runs. Excluded files are never opened.
+///
+/// # Errors
+///
+/// Will return `Err` if one of the globs or file types is invalid
+pub fn files(
+    root: &Path,
+    config: &WalkConfig,
+) -> Result<impl Iterator<Item = Result<PathBuf, ignore::Error>>, ignore::Error> {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .overrides(overrides(root, config)?)
+        .types(types(config)?)
+        .standard_filters(!config.no_ignore)
+        .hidden(!config.hidden)
+        .follow_links(config.follow)
+        // Ignore files apply even outside of git repositories
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if !config.no_ignore {
+        builder.add_custom_ignore_filename(IGNORE_FILENAME);
+    }
+
+    let files = builder
+        .build()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |e| e.file_type().is_some_and(|t| t.is_file()))
+        })
+        .map(|entry| entry.map(ignore::DirEntry::into_path));
+    Ok(files)
+}
+
+/// Builds the built-in file types together with the ones added by `config`
+/// and selects or negates them as requested
+///
+/// # Errors
+///
+/// Will return `Err` if a file type is unknown or cannot be added
+pub fn types(config: &WalkConfig) -> Result<Types, ignore::Error> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for def in &config.type_add {
+        builder.add_def(def)?;
+    }
+    for name in &config.types {
+        builder.select(name);
+    }
+    for name in &config.types_not {
+        builder.negate(name);
+    }
+    builder.build()
+}
+
+/// Builds the globs of `config`, which are matched relative to `root`
+fn overrides(root: &Path, config: &WalkConfig) -> Result<Override, ignore::Error> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in &config.globs {
+        builder.add(glob)?;
+    }
+    // Only applies to the globs added afterwards
+    builder.case_insensitive(true)?;
+    for glob in &config.iglobs {
+        builder.add(glob)?;
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{files, WalkConfig};
+    use std::fs::{self, File};
+
+    #[test]
+    fn nested_files_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("b/c")).unwrap();
+        File::create(root.join("b/c/d.txt")).unwrap();
+        File::create(root.join("b/a.txt")).unwrap();
+        File::create(root.join("a.txt")).unwrap();
+
+        let got: Vec<_> = files(root, &WalkConfig::default())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let expected = vec![
+            root.join("a.txt"),
+            root.join("b/a.txt"),
+            root.join("b/c/d.txt"),
+        ];
+
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn ignored_and_hidden_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join(".ignore"), "node_modules/\n").unwrap();
+        fs::write(root.join(".spignore"), "*.log\n!keep.log\n").unwrap();
+        File::create(root.join("target/a.txt")).unwrap();
+        File::create(root.join(".git/config")).unwrap();
+        File::create(root.join("node_modules/b.txt")).unwrap();
+        File::create(root.join(".hidden.txt")).unwrap();
+        File::create(root.join("drop.log")).unwrap();
+        File::create(root.join("keep.log")).unwrap();
+        File::create(root.join("main.rs")).unwrap();
+
+        let got: Vec<_> = files(root, &WalkConfig::default())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(got, vec![root.join("keep.log"), root.join("main.rs")]);
+
+        let config = WalkConfig {
+            hidden: true,
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        let expected = vec![
+            root.join(".git/config"),
+            root.join(".gitignore"),
+            root.join(".hidden.txt"),
+            root.join(".ignore"),
+            root.join(".spignore"),
+            root.join("keep.log"),
+            root.join("main.rs"),
+        ];
+        assert_eq!(got, expected);
+
+        let config = WalkConfig {
+            no_ignore: true,
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        let expected = vec![
+            root.join("drop.log"),
+            root.join("keep.log"),
+            root.join("main.rs"),
+            root.join("node_modules/b.txt"),
+            root.join("target/a.txt"),
+        ];
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        File::create(root.join("src/main.rs")).unwrap();
+        File::create(root.join("src/LIB.RS")).unwrap();
+        File::create(root.join("app.js")).unwrap();
+        File::create(root.join("app.min.js")).unwrap();
+
+        let config = WalkConfig {
+            globs: vec!["*.js".to_owned(), "!*.min.js".to_owned()],
+            iglobs: vec!["*.rs".to_owned()],
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        let expected = vec![
+            root.join("app.js"),
+            root.join("src/LIB.RS"),
+            root.join("src/main.rs"),
+        ];
+        assert_eq!(got, expected);
+
+        let config = WalkConfig {
+            globs: vec!["*.rs".to_owned()],
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        assert_eq!(got, vec![root.join("src/main.rs")]);
+
+        let config = WalkConfig {
+            globs: vec!["[".to_owned()],
+            ..Default::default()
+        };
+        assert!(files(root, &config).is_err());
+    }
+
+    #[test]
+    fn file_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        File::create(root.join("main.rs")).unwrap();
+        File::create(root.join("notes.md")).unwrap();
+        File::create(root.join("app.proto")).unwrap();
+        File::create(root.join("run.log")).unwrap();
+
+        let config = WalkConfig {
+            type_add: vec!["schema:*.proto".to_owned()],
+            types: vec!["rust".to_owned(), "schema".to_owned()],
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        assert_eq!(got, vec![root.join("app.proto"), root.join("main.rs")]);
+
+        let config = WalkConfig {
+            types_not: vec!["md".to_owned(), "log".to_owned()],
+            ..Default::default()
+        };
+        let got: Vec<_> = files(root, &config).unwrap().map(Result::unwrap).collect();
+        assert_eq!(got, vec![root.join("app.proto"), root.join("main.rs")]);
+
+        let config = WalkConfig {
+            types: vec!["unknown".to_owned()],
+            ..Default::default()
+        };
+        assert!(files(root, &config).is_err());
+    }
+}
@@ -1,61 +1,312 @@
-use crate::cli::Output;
-use crate::ext::BStringExt;
+use crate::ext::ByteSliceExt;
+use crate::json::{self, JsonSink, Stats};
 use crate::matcher::Config;
-use crate::results::{CountResult, GenInnerResult, GenResult, LineNumbers, SearchResult};
-use std::io::Write;
+use crate::results::LineKind;
+use crate::sink::{CountKind, Sink, SinkLine};
+use bstr::ByteSlice;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Follows the path and line number of a matching line
+const MATCH_SEPARATOR: char = ':';
+
+/// Follows the path and line number of a context line
+const CONTEXT_SEPARATOR: char = '-';
+
+/// Printed between groups of lines which are not adjacent
+const GROUP_SEPARATOR: &str = "--";
+
+// ANSI escape sequences used with --color, the same as grep's defaults
+const STYLE_PATH: &str = "\x1b[35m";
+const STYLE_LINE_NUMBER: &str = "\x1b[32m";
+const STYLE_SEPARATOR: &str = "\x1b[36m";
+const STYLE_MATCH: &str = "\x1b[1;31m";
+const STYLE_RESET: &str = "\x1b[0m";
 
 #[derive(Debug)]
 pub struct Writer<W> {
     pub wrt: W,
+    /// Totals over all inputs for the JSON summary
+    stats: Stats,
+    groups: Groups,
+}
+
+/// Keeps track of printed groups of lines over all inputs, so that like in
+/// grep, groups of different inputs are separated as well
+#[derive(Debug, Default)]
+struct Groups {
+    /// Whether a group of lines has been printed
+    printed: bool,
+    /// Whether the output is buffered to be appended to another writer, which
+    /// may have printed groups already
+    buffered: bool,
+    /// Length of the separator written before the first group of a buffer
+    leading_separator: usize,
 }
 
 impl<W: Write> Writer<W> {
-    pub fn print_matches(mut self, gen_result: GenResult, config: &Config) -> Output {
-        if let Ok(match_result) = gen_result {
-            self.print_lines_iter(match_result, config)?
+    pub fn new(wrt: W) -> Self {
+        Self {
+            wrt,
+            stats: Stats::default(),
+            groups: Groups::default(),
+        }
+    }
+
+    /// Writes everything `other` has buffered at once, so that it is never
+    /// interleaved with other output
+    pub fn append(&mut self, other: &Writer<Vec<u8>>) -> io::Result<()> {
+        // Separators only go between groups
+        let skip = if self.groups.printed {
+            0
         } else {
-            eprintln!("This error should never occur")
+            other.groups.leading_separator
         };
+        self.wrt.write_all(&other.wrt[skip..])?;
+        self.groups.printed |= other.groups.printed;
+        self.stats.add(&other.stats);
         Ok(())
     }
 
-    fn print_lines_iter(&mut self, gir: GenInnerResult, config: &Config) -> Output {
-        let nln = config.no_line_number;
-        match gir {
-            GenInnerResult::Count(count) => self.print_count(&count),
-            GenInnerResult::Search(search) => self.print_search(search, nln),
+    /// Returns a sink which prints the lines of a single input as JSON Lines
+    pub const fn json_sink<'w>(
+        &'w mut self,
+        config: &'w Config,
+        path: Option<&'w Path>,
+    ) -> JsonSink<'w, W> {
+        JsonSink::new(&mut self.wrt, &mut self.stats, config, path)
+    }
+
+    /// Prints the totals over all inputs searched with `json_sink`
+    pub fn json_summary(&mut self) -> io::Result<()> {
+        json::write_summary(&mut self.wrt, &self.stats)
+    }
+
+    /// Returns a sink which prints the lines of a single input as soon as they
+    /// are found. When `path` is given, every printed line is prefixed with it
+    /// so that results from different files can be told apart.
+    pub const fn sink<'w>(
+        &'w mut self,
+        config: &'w Config,
+        path: Option<&'w Path>,
+    ) -> WriterSink<'w, W> {
+        WriterSink {
+            wrt: &mut self.wrt,
+            groups: &mut self.groups,
+            config,
+            path,
+            previous: None,
         }
     }
+}
 
-    fn print_count(&mut self, count: &CountResult) -> Output {
-        if count.count == 0 {
-            Ok(())
+impl Writer<Vec<u8>> {
+    /// Creates a writer whose output is appended to another one with `append`
+    pub fn buffer() -> Self {
+        let mut buffer = Self::new(Vec::new());
+        buffer.groups.buffered = true;
+        buffer
+    }
+}
+
+#[derive(Debug)]
+pub struct WriterSink<'w, W> {
+    wrt: &'w mut W,
+    groups: &'w mut Groups,
+    config: &'w Config,
+    path: Option<&'w Path>,
+    /// Line number of the previously printed line of this input
+    previous: Option<u64>,
+}
+
+impl<'w, W: Write> WriterSink<'w, W> {
+    /// Writes `value` wrapped in `style` if colors are enabled
+    fn print_styled(&mut self, style: &str, value: impl Display) -> io::Result<()> {
+        if self.config.color {
+            write!(self.wrt, "{}{}{}", style, value, STYLE_RESET)
+        } else {
+            write!(self.wrt, "{}", value)
+        }
+    }
+
+    fn print_path(&mut self, separator: char) -> io::Result<()> {
+        if let Some(path) = self.path {
+            self.print_styled(STYLE_PATH, path.display())?;
+            self.print_styled(STYLE_SEPARATOR, separator)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the path, line number, column and byte offset preceding a line,
+    /// each followed by `separator`. `start` is the offset within the line of
+    /// the match to report the column of, if any
+    fn print_prefix(
+        &mut self,
+        line_number: u64,
+        start: Option<usize>,
+        byte_offset: u64,
+        separator: char,
+    ) -> io::Result<()> {
+        self.print_path(separator)?;
+        if !self.config.no_line_number {
+            self.print_styled(STYLE_LINE_NUMBER, line_number)?;
+            self.print_styled(STYLE_SEPARATOR, separator)?;
+        }
+        // Lines without a match, like context lines, have no column
+        if let Some(start) = start.filter(|_| self.config.column) {
+            self.print_styled(STYLE_LINE_NUMBER, start + 1)?;
+            self.print_styled(STYLE_SEPARATOR, separator)?;
+        }
+        if self.config.byte_offset {
+            self.print_styled(STYLE_LINE_NUMBER, byte_offset)?;
+            self.print_styled(STYLE_SEPARATOR, separator)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the separator between non-adjacent groups of lines
+    fn print_group_separator(&mut self) -> io::Result<()> {
+        let separator = if self.config.color {
+            format!("{}{}{}\n", STYLE_SEPARATOR, GROUP_SEPARATOR, STYLE_RESET)
         } else {
-            Ok(writeln!(self.wrt, "{}", count.count)?)
+            format!("{}\n", GROUP_SEPARATOR)
+        };
+        // Only a buffer can start with a separator, which `append` drops if
+        // there is no group to separate it from
+        if !self.groups.printed {
+            self.groups.leading_separator = separator.len();
         }
+        self.wrt.write_all(separator.as_bytes())
     }
 
-    fn print_search(&mut self, search: SearchResult, nln: bool) -> Output {
-        let matches = search.matches;
-        let line_numbers = search.line_numbers;
-        if nln {
-            for single_match in &matches {
-                writeln!(self.wrt, "{}", BStringExt::to_utf8(single_match))?;
+    /// Writes `bytes` as they are or, if `lossy` is set, with invalid UTF-8
+    /// replaced by U+FFFD
+    fn print_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.config.lossy {
+            write!(self.wrt, "{}", bytes.to_str_lossy())
+        } else {
+            self.wrt.write_all(bytes)
+        }
+    }
+
+    /// Writes `line` and highlights `matches` if colors are enabled
+    fn print_line(&mut self, line: &[u8], matches: &[Range<usize>]) -> io::Result<()> {
+        let mut printed = 0;
+        if self.config.color {
+            for m in matches {
+                // Matches in the terminator or overlapping the previous one
+                // cannot be highlighted
+                let start = m.start.clamp(printed, line.len());
+                let end = m.end.min(line.len());
+                if start >= end {
+                    continue;
+                }
+                self.print_bytes(&line[printed..start])?;
+                self.print_match(&line[start..end])?;
+                printed = end;
             }
-        } else if let LineNumbers::Some(lni) = line_numbers {
-            for (line_number, single_match) in lni.iter().zip(matches) {
-                writeln!(
-                    self.wrt,
-                    "{}:{}",
-                    line_number,
-                    BStringExt::to_utf8(&single_match)
-                )?;
+        }
+        self.print_bytes(&line[printed..])
+    }
+
+    /// Writes `bytes` highlighted as a match if colors are enabled
+    fn print_match(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.config.color {
+            self.wrt.write_all(STYLE_MATCH.as_bytes())?;
+            self.print_bytes(bytes)?;
+            self.wrt.write_all(STYLE_RESET.as_bytes())
+        } else {
+            self.print_bytes(bytes)
+        }
+    }
+
+    /// Writes every non-empty match of a matching line on its own line.
+    /// Context lines are not shown at all
+    fn print_only_matching(&mut self, line: &SinkLine) -> io::Result<()> {
+        if line.kind == LineKind::Context {
+            return Ok(());
+        }
+        let text = line.line.without_terminator();
+        for m in line.matches {
+            let end = m.end.min(text.len());
+            if m.start >= end {
+                continue;
             }
+            let byte_offset = line.byte_offset + m.start as u64;
+            self.print_prefix(
+                line.line_number,
+                Some(m.start),
+                byte_offset,
+                MATCH_SEPARATOR,
+            )?;
+            self.print_match(&text[m.start..end])?;
+            writeln!(self.wrt)?;
         }
         Ok(())
     }
 }
 
+impl<'w, W: Write> Sink for WriterSink<'w, W> {
+    /// Like grep, separates matches from context lines by `:` vs `-` and
+    /// non-adjacent groups of lines by `--`
+    fn line(&mut self, line: &SinkLine) -> io::Result<bool> {
+        if self.config.only_matching {
+            self.print_only_matching(line)?;
+            return Ok(true);
+        }
+
+        let context = self.config.before_context > 0 || self.config.after_context > 0;
+        let new_group = match self.previous {
+            Some(prev) => prev + 1 != line.line_number,
+            // The first group of an input follows those of previous inputs
+            None => self.groups.printed || self.groups.buffered,
+        };
+        if context && new_group {
+            self.print_group_separator()?;
+        }
+        self.previous = Some(line.line_number);
+        self.groups.printed = true;
+
+        let separator = match line.kind {
+            LineKind::Match => MATCH_SEPARATOR,
+            LineKind::Context => CONTEXT_SEPARATOR,
+        };
+        let start = line.matches.first().map(|m| m.start);
+        self.print_prefix(line.line_number, start, line.byte_offset, separator)?;
+        self.print_line(line.line.without_terminator(), line.matches)?;
+        writeln!(self.wrt)?;
+        Ok(true)
+    }
+
+    fn binary_match(&mut self) -> io::Result<()> {
+        match self.path {
+            Some(path) => writeln!(self.wrt, "Binary file {} matches", path.display()),
+            None => writeln!(self.wrt, "Binary file matches"),
+        }
+    }
+
+    fn count(&mut self, count: u64, _: CountKind) -> io::Result<()> {
+        if count == 0 {
+            Ok(())
+        } else {
+            self.print_path(MATCH_SEPARATOR)?;
+            writeln!(self.wrt, "{}", count)
+        }
+    }
+
+    fn matched(&mut self, matched: bool) -> io::Result<()> {
+        match self.path {
+            Some(path) if matched != self.config.files_without_match => {
+                self.print_styled(STYLE_PATH, path.display())?;
+                writeln!(self.wrt)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -63,8 +314,18 @@ mod tests {
     use crate::matcher::MatcherBuilder;
     use crate::search::Searcher;
     use std::fs::File;
-    use std::io::Cursor;
+    use std::io::{self, BufReader, Cursor};
     use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    /// Fails on every read, like a file which becomes unreadable halfway
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read failed"))
+        }
+    }
 
     const DICKENS: &str = "\
 He started      \r
@@ -89,21 +350,20 @@ and then stopped\
         let matcher = MatcherBuilder::new()
             .no_line_number(false)
             .max_count(None)
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut Cursor::new(DICKENS.as_bytes()),
             matcher: &matcher,
         };
 
-        let matches = searcher.search_matches();
-
         // Write to temp file
         let mut tmpfile: File = tempfile::tempfile().unwrap();
-        let wrt = Writer {
-            wrt: Write::by_ref(&mut tmpfile),
-        };
-        wrt.print_matches(matches, &matcher.config).unwrap();
+        let mut wrt = Writer::new(Write::by_ref(&mut tmpfile));
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
 
         // Seek to start (!)
         tmpfile.seek(SeekFrom::Start(0)).unwrap();
@@ -125,20 +385,59 @@ make a run
         let matcher = MatcherBuilder::new()
             .no_line_number(true)
             .max_count(Some(1))
-            .build(pattern);
+            .build(pattern)
+            .unwrap();
 
         let searcher = Searcher {
             reader: &mut Cursor::new(DICKENS.as_bytes()),
             matcher: &matcher,
         };
-        let matches = searcher.search_matches();
 
         // Write to temp file
         let mut tmpfile: File = tempfile::tempfile().unwrap();
-        let wrt = Writer {
-            wrt: Write::by_ref(&mut tmpfile),
+        let mut wrt = Writer::new(Write::by_ref(&mut tmpfile));
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
+
+        // Seek to start (!)
+        tmpfile.seek(SeekFrom::Start(0)).unwrap();
+
+        // Read back
+        let mut got = String::new();
+        tmpfile.read_to_string(&mut got).unwrap();
+
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn print_dickens_context() {
+        let expected = "\
+2:make a run
+3-& stopped.
+--
+5:made a quick run
+6-and stopped
+";
+        // Build config and matcher
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .max_count(Some(2))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: &mut Cursor::new(DICKENS.as_bytes()),
+            matcher: &matcher,
         };
-        wrt.print_matches(matches, &matcher.config).unwrap();
+
+        // Write to temp file
+        let mut tmpfile: File = tempfile::tempfile().unwrap();
+        let mut wrt = Writer::new(Write::by_ref(&mut tmpfile));
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
 
         // Seek to start (!)
         tmpfile.seek(SeekFrom::Start(0)).unwrap();
@@ -149,4 +448,184 @@ make a run
 
         assert_eq!(expected, got);
     }
+
+    #[test]
+    fn print_dickens_streamed() {
+        let expected = "\
+2:make a run
+5:made a quick run
+";
+        // Build config and matcher
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new().build(pattern).unwrap();
+
+        // Lines are printed as they are found, so everything before the
+        // failing read has already been written
+        let reader = Cursor::new(DICKENS.as_bytes()).chain(FailingReader);
+        let searcher = Searcher {
+            reader: BufReader::with_capacity(16, reader),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        let result = searcher.search_into(&mut wrt.sink(&matcher.config, None));
+
+        assert!(result.is_err());
+        assert_eq!(expected, String::from_utf8(wrt.wrt).unwrap());
+    }
+
+    #[test]
+    fn print_invalid_utf8() {
+        // Latin-1 encoded "café"
+        let latin1 = b"caf\xe9 run\nno match\n";
+
+        let pattern = "run".to_owned();
+        let raw = MatcherBuilder::new().build(pattern.clone()).unwrap();
+        let lossy = MatcherBuilder::new().lossy(true).build(pattern).unwrap();
+
+        for (matcher, expected) in [
+            (raw, &b"1:caf\xe9 run\n"[..]),
+            (lossy, "1:caf\u{FFFD} run\n".as_bytes()),
+        ] {
+            let searcher = Searcher {
+                reader: Cursor::new(&latin1[..]),
+                matcher: &matcher,
+            };
+
+            let mut wrt = Writer::new(Vec::new());
+            searcher
+                .search_into(&mut wrt.sink(&matcher.config, None))
+                .unwrap();
+
+            assert_eq!(expected, wrt.wrt);
+        }
+    }
+
+    #[test]
+    fn print_dickens_color() {
+        let expected = "\
+\x1b[32m2\x1b[0m\x1b[36m:\x1b[0mmake a \x1b[1;31mrun\x1b[0m
+\x1b[32m3\x1b[0m\x1b[36m-\x1b[0m& stopped.
+\x1b[36m--\x1b[0m
+\x1b[32m5\x1b[0m\x1b[36m:\x1b[0mmade a quick \x1b[1;31mrun\x1b[0m
+\x1b[32m6\x1b[0m\x1b[36m-\x1b[0mand stopped
+";
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .color(true)
+            .after_context(1)
+            .max_count(Some(2))
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(DICKENS.as_bytes()),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt.wrt).unwrap());
+    }
+
+    #[test]
+    fn print_dickens_only_matching() {
+        let expected = "\
+2:run
+5:run
+8:RuN
+";
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .only_matching(true)
+            .ignore_case(true)
+            .after_context(1)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(DICKENS.as_bytes()),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt.wrt).unwrap());
+    }
+
+    #[test]
+    fn print_dickens_column_byte_offset() {
+        let expected = "\
+2:8:18:make a run
+5:14:51:made a quick run
+";
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .byte_offset(true)
+            .column(true)
+            .build(pattern)
+            .unwrap();
+
+        let searcher = Searcher {
+            reader: Cursor::new(DICKENS.as_bytes()),
+            matcher: &matcher,
+        };
+
+        let mut wrt = Writer::new(Vec::new());
+        searcher
+            .search_into(&mut wrt.sink(&matcher.config, None))
+            .unwrap();
+
+        assert_eq!(expected, String::from_utf8(wrt.wrt).unwrap());
+    }
+
+    #[test]
+    fn print_context_many_inputs() {
+        let expected = "\
+a:1:run
+a-2-b
+--
+b:1:run
+b-2-b
+";
+        let pattern = "run".to_owned();
+        let matcher = MatcherBuilder::new()
+            .after_context(1)
+            .build(pattern)
+            .unwrap();
+
+        // Groups of different inputs are separated, whether they are printed
+        // directly or buffered first
+        let mut direct = Writer::new(Vec::new());
+        let mut appended = Writer::new(Vec::new());
+        for path in &["a", "b"] {
+            let label = Some(Path::new(path));
+            let searcher = Searcher {
+                reader: Cursor::new(&b"run\nb\n"[..]),
+                matcher: &matcher,
+            };
+            searcher
+                .search_into(&mut direct.sink(&matcher.config, label))
+                .unwrap();
+
+            let searcher = Searcher {
+                reader: Cursor::new(&b"run\nb\n"[..]),
+                matcher: &matcher,
+            };
+            let mut buffer = Writer::buffer();
+            searcher
+                .search_into(&mut buffer.sink(&matcher.config, label))
+                .unwrap();
+            appended.append(&buffer).unwrap();
+        }
+
+        assert_eq!(expected, String::from_utf8(direct.wrt).unwrap());
+        assert_eq!(expected, String::from_utf8(appended.wrt).unwrap());
+    }
 }
@@ -24,7 +24,7 @@ fn find_content_in_file() -> Result<(), Box<dyn Error>> {
     )?;
 
     let mut cmd = Command::cargo_bin("sp")?;
-    cmd.arg("test").arg(file.path());
+    cmd.arg("test").arg(file.path()).arg("-a");
     cmd.assert()
         .success()
         .stdout(predicate::str::contains("1:A test\n4:A\x00nother test"));
@@ -40,7 +40,11 @@ fn find_content_in_file_no_line_number() -> Result<(), Box<dyn Error>> {
         "A test\nActual content\r\nMore content\nA\x00nother test"
     )?;
     let mut cmd = Command::cargo_bin("sp")?;
-    cmd.arg("Test").arg(file.path()).arg("-n").arg("-i");
+    cmd.arg("Test")
+        .arg(file.path())
+        .arg("-n")
+        .arg("-i")
+        .arg("-a");
     cmd.assert()
         .success()
         .stdout(predicate::str::contains("A test\nA\x00nother test"));
@@ -56,7 +60,7 @@ fn find_content_in_file_line_number() -> Result<(), Box<dyn Error>> {
         "A test\nActual content\r\nMore content\nA\x00nother test"
     )?;
     let mut cmd = Command::cargo_bin("sp")?;
-    cmd.arg("test").arg(file.path()).arg("-m=1");
+    cmd.arg("test").arg(file.path()).arg("-m=1").arg("-a");
     cmd.assert()
         .success()
         .stdout(predicate::str::similar("1:A test\n"));
@@ -94,3 +98,722 @@ fn count_match() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn count_match_max_count() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(
+        file,
+        "A test\nActual content\nMore content\nA\x00nother test"
+    )?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("A")
+        .arg(file.path())
+        .arg("-c")
+        .arg("-i")
+        .arg("-m")
+        .arg("2");
+    cmd.assert().success().stdout("2\n");
+
+    Ok(())
+}
+
+#[test]
+fn search_directory_recursively() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("sub"))?;
+    std::fs::write(dir.path().join("a.txt"), "A test\nActual content\n")?;
+    std::fs::write(
+        dir.path().join("sub").join("b.txt"),
+        "More content\nAnother test\n",
+    )?;
+
+    let expected = format!(
+        "{}:1:A test\n{}:2:Another test\n",
+        dir.path().join("a.txt").display(),
+        dir.path().join("sub").join("b.txt").display()
+    );
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn search_multiple_files() -> Result<(), Box<dyn Error>> {
+    let mut first = NamedTempFile::new()?;
+    writeln!(first, "A test\nActual content")?;
+    let mut second = NamedTempFile::new()?;
+    writeln!(second, "More content\nAnother test")?;
+
+    let expected = format!(
+        "{}:1:A test\n{}:2:Another test\n",
+        first.path().display(),
+        second.path().display()
+    );
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(first.path()).arg(second.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn search_stdin() -> Result<(), Box<dyn Error>> {
+    let mut cmd = assert_cmd::Command::cargo_bin("sp")?;
+    cmd.arg("test").write_stdin("A test\nActual content\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:A test\n"));
+
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "Another test")?;
+
+    let expected = format!(
+        "(standard input):1:A test\n{}:1:Another test\n",
+        file.path().display()
+    );
+
+    let mut cmd = assert_cmd::Command::cargo_bin("sp")?;
+    cmd.arg("test")
+        .arg("-")
+        .arg(file.path())
+        .write_stdin("A test\nActual content\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn search_continues_after_missing_file() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "A test")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test")
+        .arg("test/file/doesnt/exists")
+        .arg(file.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("1:A test"))
+        .stderr(predicate::str::contains("No such file or directory"));
+
+    Ok(())
+}
+
+#[test]
+fn regex_search() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(
+        file,
+        "A test\nActual content\nMore content\nA\x00nother test"
+    )?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("^a.*t$")
+        .arg(file.path())
+        .arg("-E")
+        .arg("-i")
+        .arg("-a");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "1:A test\n2:Actual content\n4:A\x00nother test\n",
+    ));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("(unclosed").arg(file.path()).arg("-E");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unclosed group"));
+
+    Ok(())
+}
+
+#[test]
+fn invert_match() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(
+        file,
+        "A test\nActual content\nMore content\nA\x00nother test"
+    )?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path()).arg("-v").arg("-a");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "2:Actual content\n3:More content\n",
+    ));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("content").arg(file.path()).arg("-v").arg("-c");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("2\n"));
+
+    Ok(())
+}
+
+#[test]
+fn context_lines() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "a\nrun\nb\nc\nd\nrun\ne")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("run").arg(file.path()).arg("-C=1");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "1-a\n2:run\n3-b\n--\n5-d\n6:run\n7-e\n",
+    ));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("run").arg(file.path()).arg("-B=1").arg("-n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("a\nrun\n--\nd\nrun\n"));
+
+    Ok(())
+}
+
+#[test]
+fn context_lines_many_files() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let first = dir.path().join("c1");
+    let second = dir.path().join("c2");
+    std::fs::write(&first, "foo\nx\n")?;
+    std::fs::write(&second, "foo\ny\n")?;
+
+    let expected = format!(
+        "{}:1:foo\n{}-2-x\n--\n{}:1:foo\n{}-2-y\n",
+        first.display(),
+        first.display(),
+        second.display(),
+        second.display()
+    );
+
+    for threads in &["1", "4"] {
+        let mut cmd = Command::cargo_bin("sp")?;
+        cmd.arg("-j").arg(threads);
+        cmd.arg("-A").arg("1").arg("foo").arg(&first).arg(&second);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::similar(expected.clone()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn invalid_utf8_output() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    file.write_all(b"caf\xe9 run\nno match\n")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout(&b"1:caf\xe9 run\n"[..]);
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("run").arg(file.path()).arg("--lossy");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:caf\u{FFFD} run\n"));
+
+    Ok(())
+}
+
+#[test]
+fn binary_file() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "A test\nA\x00nother test")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("Binary file matches\n"));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path()).arg("--binary=skip");
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test")
+        .arg(file.path())
+        .arg("--binary=skip")
+        .arg("-a");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:A test\n2:A\x00nother test\n"));
+
+    Ok(())
+}
+
+#[test]
+fn color_output() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "A test\nActual content")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path()).arg("--color=always");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0mA \x1b[1;31mtest\x1b[0m\n",
+    ));
+
+    // Output is not a terminal
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:A test\n"));
+
+    Ok(())
+}
+
+#[test]
+fn only_matching() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "id=42 id=7\nnone\nid=1")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("id=[0-9]+").arg(file.path()).arg("-E").arg("-o");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:id=42\n1:id=7\n3:id=1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn column_and_byte_offset() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "A test\nthe test test")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path()).arg("--column").arg("-b");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "1:3:0:A test\n2:5:7:the test test\n",
+    ));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test")
+        .arg(file.path())
+        .arg("--column")
+        .arg("-b")
+        .arg("-o");
+    cmd.assert().success().stdout(predicate::str::similar(
+        "1:3:2:test\n2:5:11:test\n2:10:16:test\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn json_output() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "A test\nActual content")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("test").arg(file.path()).arg("--json");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(r#"{"data":{"path":{"text":"#))
+        .stdout(predicate::str::contains(
+            r#""line_number":1,"lines":{"text":"A test\n"}"#,
+        ))
+        .stdout(predicate::str::contains(r#""submatches":[{"end":6,"match":{"text":"test"},"start":2}]"#))
+        .stdout(predicate::str::ends_with(
+            "{\"data\":{\"stats\":{\"matched_lines\":1,\"matches\":1,\"searches\":1,\"searches_with_match\":1}},\"type\":\"summary\"}\n",
+        ));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("sp")?;
+    cmd.arg("test")
+        .arg("--json")
+        .arg("-c")
+        .write_stdin("A test\n");
+    cmd.assert().success().stdout(predicate::str::starts_with(
+        r#"{"data":{"count":1,"path":{"text":"(standard input)"}},"type":"count"}"#,
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn many_patterns() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "E100 failed\nE200 ok\nall good\nE300 failed")?;
+    let mut patterns = NamedTempFile::new()?;
+    writeln!(patterns, "E200\nE300")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-e")
+        .arg("E100")
+        .arg("-e")
+        .arg("good")
+        .arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:E100 failed\n3:all good\n"));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-f")
+        .arg(patterns.path())
+        .arg("-e")
+        .arg("E1")
+        .arg(file.path());
+    cmd.arg("--starts-with").arg("-c");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("3\n"));
+
+    Ok(())
+}
+
+#[test]
+fn line_regexp() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(
+        file,
+        "alice
+bob smith
+Bob
+mallory"
+    )?;
+    let mut allowlist = NamedTempFile::new()?;
+    writeln!(
+        allowlist,
+        "bob
+alice"
+    )?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-x").arg("-i").arg("-f").arg(allowlist.path());
+    cmd.arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:alice\n3:Bob\n"));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-x")
+        .arg("-v")
+        .arg("-c")
+        .arg("-f")
+        .arg(allowlist.path());
+    cmd.arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("3\n"));
+
+    Ok(())
+}
+
+#[test]
+fn smart_case() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "Γain\nγain\nGAIN")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-S").arg("γain").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:Γain\n2:γain\n"));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-S").arg("Γain").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:Γain\n"));
+
+    Ok(())
+}
+
+#[test]
+fn caseless_unicode() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "ΣΊΣΥΦΟΣ Straße\nσίσυφος")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-o")
+        .arg("-i")
+        .arg("-e")
+        .arg("strasse")
+        .arg("-e")
+        .arg("Σίσυφος");
+    cmd.arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:ΣΊΣΥΦΟΣ\n1:Straße\n2:σίσυφος\n"));
+
+    // A regex only uses simple case folding
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-o").arg("-i").arg("-E").arg("strasse|σίσυφος");
+    cmd.arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("1:ΣΊΣΥΦΟΣ\n2:σίσυφος\n"));
+
+    Ok(())
+}
+
+#[test]
+fn count_matches() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "run run\nstop\nrun a run, rerun")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("--count-matches").arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("5\n"));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("--count-matches")
+        .arg("-w")
+        .arg("run")
+        .arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar("4\n"));
+
+    Ok(())
+}
+
+#[test]
+fn files_with_matches() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("a.txt"), "run\nstop\n")?;
+    std::fs::write(dir.path().join("b.txt"), "stop\n")?;
+    std::fs::write(dir.path().join("c.txt"), "stop\nrun\nrun\n")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("-c").arg("run").arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    let expected = format!(
+        "{}\n{}\n",
+        dir.path().join("a.txt").display(),
+        dir.path().join("c.txt").display()
+    );
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("--files-without-match").arg("run").arg(dir.path());
+    let expected = format!("{}\n", dir.path().join("b.txt").display());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("run").arg(dir.path().join("a.txt"));
+    let expected = format!("{}\n", dir.path().join("a.txt").display());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn ignore_files_and_hidden() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("target"))?;
+    std::fs::write(dir.path().join(".gitignore"), "target/\n")?;
+    std::fs::write(dir.path().join("target").join("a.txt"), "run\n")?;
+    std::fs::write(dir.path().join(".b.txt"), "run\n")?;
+    std::fs::write(dir.path().join("c.txt"), "run\n")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("run").arg(dir.path());
+    let expected = format!("{}\n", dir.path().join("c.txt").display());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("--hidden").arg("--no-ignore").arg("run");
+    cmd.arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    let expected = format!(
+        "{}\n{}\n{}\n",
+        dir.path().join(".b.txt").display(),
+        dir.path().join("c.txt").display(),
+        dir.path().join("target").join("a.txt").display()
+    );
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn follow_symlinks() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let other = tempfile::tempdir()?;
+    std::fs::write(other.path().join("a.txt"), "run\n")?;
+    std::os::unix::fs::symlink(other.path(), dir.path().join("link"))?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("run").arg(dir.path());
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("--follow").arg("run").arg(dir.path());
+    let expected = format!("{}\n", dir.path().join("link").join("a.txt").display());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn glob_filters() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("app.js"), "run\n")?;
+    std::fs::write(dir.path().join("app.min.js"), "run\n")?;
+    std::fs::write(dir.path().join("MAIN.RS"), "run\n")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l")
+        .arg("-g")
+        .arg("*.js")
+        .arg("-g")
+        .arg("!*.min.js");
+    cmd.arg("--iglob").arg("*.rs").arg("run").arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    let expected = format!(
+        "{}\n{}\n",
+        dir.path().join("MAIN.RS").display(),
+        dir.path().join("app.js").display()
+    );
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-g").arg("[").arg("run").arg(dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("glob"));
+
+    Ok(())
+}
+
+#[test]
+fn file_types() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("main.rs"), "run\n")?;
+    std::fs::write(dir.path().join("notes.md"), "run\n")?;
+    std::fs::write(dir.path().join("app.schema"), "run\n")?;
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("--type-add").arg("schema:*.schema");
+    cmd.arg("-t").arg("schema").arg("-t").arg("rust").arg("run");
+    cmd.arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    let expected = format!(
+        "{}\n{}\n",
+        dir.path().join("app.schema").display(),
+        dir.path().join("main.rs").display()
+    );
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-l").arg("-T").arg("md").arg("run").arg(dir.path());
+    cmd.arg("--sort").arg("path");
+    let expected = format!(
+        "{}\n{}\n",
+        dir.path().join("app.schema").display(),
+        dir.path().join("main.rs").display()
+    );
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("--type-list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("\nrust: *.rs\n"));
+
+    Ok(())
+}
+
+#[test]
+fn parallel_sorted_output() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut expected = String::new();
+    for i in 0..50 {
+        let path = dir.path().join(format!("{:02}.txt", i));
+        std::fs::write(&path, "run\nstop\nrun\n")?;
+        expected.push_str(&format!(
+            "{}:1:run\n{}:3:run\n",
+            path.display(),
+            path.display()
+        ));
+    }
+
+    for threads in &["1", "4"] {
+        let mut cmd = Command::cargo_bin("sp")?;
+        cmd.arg("-j").arg(threads).arg("--sort").arg("path");
+        cmd.arg("run").arg(dir.path());
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::similar(expected.clone()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn parallel_argument_order() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut paths = Vec::new();
+    let mut expected = String::new();
+    for i in (0..50).rev() {
+        let path = dir.path().join(format!("{:02}.txt", i));
+        std::fs::write(&path, "run\n")?;
+        expected.push_str(&format!("{}:1:run\n", path.display()));
+        paths.push(path);
+    }
+
+    // Explicit paths are printed in the order in which they are given
+    let mut cmd = Command::cargo_bin("sp")?;
+    cmd.arg("-j").arg("4").arg("run").args(&paths);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::similar(expected));
+
+    Ok(())
+}
+
+#[test]
+fn mmap_search() -> Result<(), Box<dyn Error>> {
+    let mut file = NamedTempFile::new()?;
+    for i in 1..=3000 {
+        writeln!(file, "line {}", i)?;
+    }
+    // A NUL byte beyond the first block does not make the file binary
+    writeln!(file, "a\0run")?;
+
+    for mmap in &["--mmap", "--no-mmap"] {
+        let mut cmd = Command::cargo_bin("sp")?;
+        cmd.arg(mmap).arg("run").arg(file.path());
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::similar("3001:a\0run\n"));
+    }
+
+    Ok(())
+}